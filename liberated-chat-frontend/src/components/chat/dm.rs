@@ -0,0 +1,159 @@
+use super::{Conversation, Cursor, DirectMessage, Target};
+use leptos::{
+    component, create_effect, create_rw_signal, event_target_value, on_cleanup,
+    set_interval_with_handle, spawn_local, store_value, view, CollectView, IntoView, ReadSignal,
+    RwSignal, Show, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate,
+};
+use std::time::Duration;
+
+//Direct messages have no live feed, so open conversations check for new ones this often
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[component]
+pub fn Conversations(target: RwSignal<Target>, sent: ReadSignal<u64>) -> impl IntoView {
+    let conversations = create_rw_signal(Vec::<Conversation>::new());
+    let new_user = create_rw_signal(String::new());
+    let status = create_rw_signal(String::new());
+
+    //Reload after every send, a new conversation may have just started
+    create_effect(move |_| {
+        sent.get();
+
+        spawn_local(async move {
+            match Conversation::list().await {
+                Ok(v) => conversations.set(v),
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    });
+
+    let open_fn = move || {
+        let user = new_user.get_untracked().trim().to_string();
+
+        if !user.is_empty() {
+            target.set(Target::Direct(user));
+        }
+    };
+
+    view! {
+        <div class="p-2 space-y-2">
+            <h2 class="font-semibold">Direct messages</h2>
+            {move || {
+                conversations
+                    .get()
+                    .into_iter()
+                    .map(|conversation| {
+                        let user = conversation.user.clone();
+                        let selected = Target::Direct(conversation.user.clone());
+                        view! {
+                            <button
+                                class=move || {
+                                    if target.get() == selected {
+                                        "block w-full text-left rounded-md px-2 py-1 text-sm bg-neutral-800"
+                                    } else {
+                                        "block w-full text-left rounded-md px-2 py-1 text-sm hover:bg-neutral-800"
+                                    }
+                                }
+
+                                on:click=move |_| target.set(Target::Direct(user.clone()))
+                            >
+                                <div>{conversation.user}</div>
+                                <div class="text-xs text-gray-500 truncate">
+                                    {conversation.last_message.message}
+                                </div>
+                            </button>
+                        }
+                    })
+                    .collect_view()
+            }}
+
+            <div class="flex rounded-lg border">
+                <input
+                    class="min-w-0 flex-1 rounded-l-lg bg-neutral-800 p-1 text-sm"
+                    placeholder="Username"
+                    on:change=move |ev| {
+                        new_user.set(event_target_value(&ev));
+                    }
+                />
+                <button
+                    class="rounded-r-lg bg-neutral-900 px-2 text-sm"
+                    on:click=move |_| {
+                        open_fn();
+                    }
+                >
+
+                    Open
+                </button>
+            </div>
+            <p class="text-xs">{status}</p>
+        </div>
+    }
+}
+
+#[component]
+pub fn DirectMessages(user: String, sent: ReadSignal<u64>) -> impl IntoView {
+    let messages = create_rw_signal(Vec::<DirectMessage>::new());
+    let result = create_rw_signal(String::new());
+    let user = store_value(user);
+
+    //Fetches the newest page on first load, otherwise only what came in since
+    let load_fn = move || {
+        spawn_local(async move {
+            let cursor = messages
+                .get_untracked()
+                .last()
+                .map_or(Cursor::Latest, |message| Cursor::After(message.dm_num));
+
+            match DirectMessage::fetch(&user.get_value(), cursor).await {
+                Ok(page) => {
+                    result.set(String::new());
+                    messages.update(|messages| {
+                        messages.extend(page);
+                        messages.sort_by_key(|message| message.dm_num);
+                        messages.dedup_by_key(|message| message.dm_num);
+                    });
+                }
+                Err(e) => result.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    create_effect(move |_| {
+        sent.get();
+        load_fn();
+    });
+
+    if let Ok(handle) = set_interval_with_handle(load_fn, POLL_INTERVAL) {
+        on_cleanup(move || handle.clear());
+    }
+
+    view! {
+        <div class="h-[calc(100vh-12rem)] overflow-y-auto p-4 space-y-2">
+            <h2 class="font-semibold">{format!("Conversation with {}", user.get_value())}</h2>
+            <Show
+                when=move || { result.get().is_empty() }
+                fallback=move || {
+                    view! { <h1 class="text-red">{format!("Error: {}", result.get())}</h1> }
+                }
+            >
+
+                {move || {
+                    messages
+                        .get()
+                        .iter()
+                        .map(|message| {
+                            view! {
+                                <super::message::Message
+                                    username=&message.from
+                                    message=&message.message
+                                    time=&message.time
+                                />
+                            }
+                        })
+                        .collect_view()
+                }}
+
+            </Show>
+        </div>
+    }
+}
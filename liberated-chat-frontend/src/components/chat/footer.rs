@@ -1,18 +1,58 @@
 use leptos::{
-    component, create_signal, event_target_value, spawn_local, view, IntoView, SignalGet, SignalSet,
+    component, create_node_ref, create_signal, event_target_value, html, spawn_local, view,
+    IntoView, ReadSignal, RwSignal, Show, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate,
+    SignalWith,
 };
 
 #[component]
-pub fn Footer() -> impl IntoView {
+pub fn Footer(target: ReadSignal<super::Target>, sent: RwSignal<u64>) -> impl IntoView {
     let (message, set_message) = create_signal(String::new());
     let (status, set_status) = create_signal(String::new());
+    //Same as the server's default until the real one arrives
+    let (max_length, set_max_length) = create_signal(2000);
+
+    spawn_local(async move {
+        if let Ok(limits) = super::Limits::get().await {
+            set_max_length.set(limits.max_message_length);
+        }
+    });
+
+    let length = move || message.with(|m| m.chars().count());
+
+    //Only posts in rooms can carry attachments
+    let file_input = create_node_ref::<html::Input>();
 
     let send_fn = move || {
         spawn_local(async move {
             let msg = move || message.get();
-            match crate::utils::auth::send_message(msg()).await {
-                Ok(_) => (),
-                Err(e) => set_status.set(format!("{e:?}")),
+            let res = match target.get_untracked() {
+                super::Target::Room(room) => {
+                    let files = file_input
+                        .get_untracked()
+                        .and_then(|input| input.files())
+                        .filter(|files| files.length() > 0);
+
+                    match files {
+                        Some(files) => crate::utils::posts::upload(room, msg(), files).await,
+                        None => crate::utils::auth::send_message(room, msg()).await,
+                    }
+                }
+                super::Target::Direct(user) => {
+                    crate::utils::dm::send_direct_message(&user, msg()).await
+                }
+            };
+
+            match res {
+                Ok(_) => {
+                    set_status.set(String::new());
+
+                    if let Some(input) = file_input.get_untracked() {
+                        input.set_value("");
+                    }
+
+                    sent.update(|n| *n += 1)
+                }
+                Err(e) => set_status.set(e.to_string()),
             }
         });
     };
@@ -24,7 +64,7 @@ pub fn Footer() -> impl IntoView {
                     <textarea
                         class="min-h-[60px] flex-1 rounded-l-lg bg-neutral-800 p-2"
                         placeholder="Enter your message"
-                        on:change=move |ex| {
+                        on:input=move |ex| {
                             set_message.set(event_target_value(&ex));
                         }
                     >
@@ -39,7 +79,22 @@ pub fn Footer() -> impl IntoView {
                         Send
                     </button>
                 </div>
-                <p>{status}</p>
+                <div class="flex justify-between text-sm">
+                    <Show
+                        when=move || matches!(target.get(), super::Target::Room(_))
+                        fallback=move || view! {}
+                    >
+                        <input type="file" multiple class="text-xs" node_ref=file_input/>
+                    </Show>
+                    <p>{status}</p>
+                    <p class=move || {
+                        if length() > max_length.get() { "text-red-500" } else { "text-gray-500" }
+                    }>
+                        {length}
+                        "/"
+                        {max_length}
+                    </p>
+                </div>
             </div>
         </div>
     }
@@ -1,97 +1,736 @@
+use super::{merge_posts, Attachment, Cursor, Jump, Post, Reaction, Thread, PAGE_SIZE};
 use leptos::{
-    component, create_signal, spawn_local, view, CollectView, IntoView, Show, SignalGet, SignalSet,
+    component, create_effect, create_memo, create_node_ref, create_rw_signal, document, ev,
+    event_target_value, html, on_cleanup, request_animation_frame, set_timeout, spawn_local, view,
+    window, Children, CollectView, For, IntoView, NodeRef, ReadSignal, RwSignal, Show, SignalGet,
+    SignalGetUntracked, SignalSet, SignalUpdate, SignalWith, View,
 };
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+use web_sys::WebSocket;
 
-#[component]
-pub fn Messages() -> impl IntoView {
-    let (result, set_result) = create_signal(String::new());
-    let (messages, set_messages) = create_signal(Vec::new());
-    let (first_click, set_first_click) = create_signal(true);
+//Longest wait between reconnect attempts, in seconds
+const MAX_RECONNECT_DELAY: u64 = 30;
+//How close to the top of the list, in pixels, before older posts are fetched
+const LOAD_OLDER_THRESHOLD: i32 = 64;
+//Offered by the + button under each post
+const QUICK_REACTIONS: &[&str] = &["👍", "❤️", "😂", "😮", "😢", "🎉"];
+//How long a post jumped to stays highlighted, in seconds
+const FOCUS_HIGHLIGHT: u64 = 3;
+
+#[derive(Default)]
+struct LiveFeed {
+    socket: RefCell<Option<WebSocket>>,
+    retries: Cell<u32>,
+    stopped: Cell<bool>,
+}
+
+#[derive(Clone, Copy)]
+struct PostList {
+    room: u64,
+    //Replies are kept in here as well, but only shown in their thread
+    messages: RwSignal<Vec<Post>>,
+    //Post numbers of the threads that have been opened
+    open_threads: RwSignal<Vec<u64>>,
+    //Post being jumped to from a search result
+    focused: RwSignal<Option<u64>>,
+    result: RwSignal<String>,
+    has_older: RwSignal<bool>,
+    loading_older: RwSignal<bool>,
+    scroller: NodeRef<html::Div>,
+}
 
-    let load_messages_fn = move || {
+impl PostList {
+    fn scroll_to_bottom(self) {
+        request_animation_frame(move || {
+            if let Some(el) = self.scroller.get_untracked() {
+                el.set_scroll_top(el.scroll_height());
+            }
+        });
+    }
+
+    fn scroll_to_post(self, post_num: u64) {
+        request_animation_frame(move || {
+            if let Some(el) = document().get_element_by_id(&format!("post-{post_num}")) {
+                el.scroll_into_view();
+            }
+        });
+
+        set_timeout(
+            move || {
+                if self.focused.get_untracked() == Some(post_num) {
+                    self.focused.set(None);
+                }
+            },
+            Duration::from_secs(FOCUS_HIGHLIGHT),
+        );
+    }
+
+    fn is_at_bottom(self) -> bool {
+        self.scroller.get_untracked().is_none_or(|el| {
+            el.scroll_height() - el.scroll_top() - el.client_height() < LOAD_OLDER_THRESHOLD
+        })
+    }
+
+    //Fetches the newest page on first load, or the page ending at the focused post and everything
+    //after it. Otherwise fetches everything posted since the last known post
+    fn catch_up(self) {
         spawn_local(async move {
-            set_first_click.set(false);
+            let last = self
+                .messages
+                .get_untracked()
+                .last()
+                .map(|post| post.post_num);
 
-            match super::Post::new().await {
-                Ok(v) => {
-                    set_result.set(String::new());
-                    set_messages.set(v)
+            let focused = self.focused.get_untracked();
+            let at_bottom = self.is_at_bottom();
+
+            let mut cursor = match (last, focused) {
+                (Some(post_num), _) => Cursor::After(post_num),
+                (None, Some(post_num)) => Cursor::Before(post_num + 1),
+                (None, None) => Cursor::Latest,
+            };
+
+            loop {
+                let first_load = !matches!(cursor, Cursor::After(_));
+
+                let page = match Post::fetch(self.room, cursor).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        self.result.set(format!("{e:?}"));
+                        return;
+                    }
+                };
+
+                self.result.set(String::new());
+
+                let full = page.len() as u64 == PAGE_SIZE;
+                let newest = page.last().map(|post| post.post_num);
+
+                if first_load {
+                    self.has_older.set(full);
                 }
-                Err(e) => set_result.set(format!("{e:?}")),
+
+                self.messages.update(|posts| merge_posts(posts, page));
+
+                //Starting from a focused post there are always newer posts to work forward to
+                cursor = match (cursor, newest) {
+                    (Cursor::Before(_), Some(post_num)) => Cursor::After(post_num),
+                    (Cursor::After(_), Some(post_num)) if full => Cursor::After(post_num),
+                    _ => break,
+                };
+            }
+
+            //A focused post scrolls itself into view once it is drawn
+            if at_bottom && self.focused.get_untracked().is_none() {
+                self.scroll_to_bottom();
             }
         });
+    }
+
+    //Reloads the list around a post, so it can be shown however far back it is
+    fn jump_to(self, post_num: u64) {
+        self.focused.set(Some(post_num));
+        self.messages.set(Vec::new());
+        self.has_older.set(false);
+        self.catch_up();
+    }
+
+    fn load_older(self) {
+        if self.loading_older.get_untracked() || !self.has_older.get_untracked() {
+            return;
+        }
+
+        let first = match self.messages.get_untracked().first() {
+            Some(post) => post.post_num,
+            None => return,
+        };
+
+        self.loading_older.set(true);
+
+        spawn_local(async move {
+            match Post::fetch(self.room, Cursor::Before(first)).await {
+                Ok(page) => {
+                    self.has_older.set(page.len() as u64 == PAGE_SIZE);
+
+                    //Keep the posts being read in place as older ones are added above them
+                    let old_height = self.scroller.get_untracked().map(|el| el.scroll_height());
+
+                    self.messages.update(|posts| merge_posts(posts, page));
+
+                    request_animation_frame(move || {
+                        if let (Some(el), Some(old_height)) =
+                            (self.scroller.get_untracked(), old_height)
+                        {
+                            el.set_scroll_top(el.scroll_top() + el.scroll_height() - old_height);
+                        }
+                    });
+                }
+                Err(e) => self.result.set(format!("{e:?}")),
+            }
+
+            self.loading_older.set(false);
+        });
+    }
+}
+
+fn subscribe(feed: Rc<LiveFeed>, list: PostList) {
+    if feed.stopped.get() {
+        return;
+    }
+
+    let on_open = {
+        let feed = feed.clone();
+        move || {
+            feed.retries.set(0);
+            //Catch up on anything posted while we were disconnected
+            list.catch_up();
+        }
+    };
+
+    let on_post = move |json: String| {
+        if let Ok(post) = serde_json::from_str::<Post>(&json) {
+            //The socket carries posts from every room we are in
+            if post.room != list.room {
+                return;
+            }
+
+            let at_bottom = list.is_at_bottom();
+
+            list.messages.update(|posts| merge_posts(posts, vec![post]));
+
+            if at_bottom {
+                list.scroll_to_bottom();
+            }
+        }
+    };
+
+    let on_close = {
+        let feed = feed.clone();
+        move || {
+            feed.socket.replace(None);
+
+            if feed.stopped.get() {
+                return;
+            }
+
+            let retries = feed.retries.get();
+            feed.retries.set(retries + 1);
+
+            let delay = 2u64.saturating_pow(retries).min(MAX_RECONNECT_DELAY);
+            set_timeout(move || subscribe(feed, list), Duration::from_secs(delay));
+        }
+    };
+
+    match crate::utils::live::connect(on_open, on_post, on_close) {
+        Ok(socket) => {
+            feed.socket.replace(Some(socket));
+        }
+        Err(e) => list.result.set(format!("{e:?}")),
+    }
+}
+
+#[component]
+pub fn Messages(room: u64, me: ReadSignal<String>, jump: RwSignal<Option<Jump>>) -> impl IntoView {
+    let list = PostList {
+        room,
+        messages: create_rw_signal(Vec::new()),
+        open_threads: create_rw_signal(Vec::new()),
+        focused: create_rw_signal(None),
+        result: create_rw_signal(String::new()),
+        has_older: create_rw_signal(false),
+        loading_older: create_rw_signal(false),
+        scroller: create_node_ref::<html::Div>(),
     };
 
+    //Jumps to other rooms are picked up by their own list once it is showing
+    create_effect(move |_| {
+        if let Some(to) = jump.get().filter(|to| to.room == room) {
+            jump.set(None);
+
+            if let Some(parent) = to.parent_post_num {
+                list.open_threads.update(|threads| {
+                    if !threads.contains(&parent) {
+                        threads.push(parent);
+                    }
+                });
+            }
+
+            list.jump_to(to.post_num);
+        }
+    });
+
+    let feed = Rc::new(LiveFeed::default());
+
+    subscribe(feed.clone(), list);
+
+    on_cleanup(move || {
+        feed.stopped.set(true);
+
+        if let Some(socket) = feed.socket.take() {
+            _ = socket.close();
+        }
+    });
+
+    let scroller = list.scroller;
+
     view! {
-        <button
-            class="whitespace-nowrap shrink-0 font-medium transition-colors focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-ring disabled:pointer-events-none shadow-sm hover:bg-secondary/80 text-xs hidden md:flex md:flex-col bg-black text-zinc-500  md:aspect-video text-center items-center justify-center gap-2 cursor-pointer rounded-lg h-auto w-auto  disabled:opacity-50 disabled:cursor-pointer pointer-events-auto max-h-full basis-full md:basis-auto py-2 md:py-2.5 px-2.5"
-            on:click=move |_| {
-                load_messages_fn();
+        <div
+            class="h-[calc(100vh-12rem)] overflow-y-auto p-4 space-y-2"
+            node_ref=scroller
+            on:scroll=move |_: ev::Event| {
+                if let Some(el) = list.scroller.get_untracked() {
+                    if el.scroll_top() < LOAD_OLDER_THRESHOLD {
+                        list.load_older();
+                    }
+                }
             }
         >
 
-            <svg
-                xmlns="http://www.w3.org/2000/svg"
-                width="15"
-                height="15"
-                viewBox="0 0 24 24"
-                fill="none"
-                stroke="currentColor"
-                stroke-width="2"
-                stroke-linecap="round"
-                stroke-linejoin="round"
-                class="w-4 h-4"
+            <Show when=move || { list.loading_older.get() } fallback=move || view! {}>
+                <p class="text-xs text-center text-gray-500">Loading older messages...</p>
+            </Show>
+
+            <Show
+                when=move || { list.result.get().is_empty() }
+                fallback=move || {
+                    view! { <h1 class="text-red">{format!("Error: {}", list.result.get())}</h1> }
+                }
             >
-                <path d="M3 12a9 9 0 0 1 9-9 9.75 9.75 0 0 1 6.74 2.74L21 8"></path>
-                <path d="M21 3v5h-5"></path>
-                <path d="M21 12a9 9 0 0 1-9 9 9.75 9.75 0 0 1-6.74-2.74L3 16"></path>
-                <path d="M8 16H3v5"></path>
-            </svg>
-            <span>Reload</span>
-        </button>
 
-        <Show
-            when=move || { first_click.get() }
-            fallback=move || {
-                view! {}
+                <For
+                    each=move || {
+                        list.messages
+                            .get()
+                            .into_iter()
+                            .filter(|post| post.parent_post_num.is_none())
+                            .collect::<Vec<_>>()
+                    }
+                    key=post_key
+                    children=move |post| post_view(post, me, list)
+                />
+
+            </Show>
+        </div>
+    }
+}
+
+//Posts are redrawn whenever one of these changes
+fn post_key(post: &Post) -> (u64, Option<String>, bool, Vec<Reaction>) {
+    (
+        post.post_num,
+        post.edited_at.clone(),
+        post.deleted,
+        post.reactions.clone(),
+    )
+}
+
+//A post with everything that can be done to it, and its thread if it can have one.
+//Returns a View since threads are made of posts in turn
+fn post_view(post: Post, me: ReadSignal<String>, list: PostList) -> View {
+    let author = post.user.clone();
+    let deleted = post.deleted;
+    //Deleted posts can't be replied to, but existing replies can still be read
+    let has_thread = post.parent_post_num.is_none() && (!deleted || post.reply_count > 0);
+    let post_num = post.post_num;
+    //The controls are drawn inside closures, so they get their own copies
+    let message = post.message.clone();
+    let reactions = post.reactions.clone();
+
+    if list.focused.get_untracked() == Some(post_num) {
+        list.scroll_to_post(post_num);
+    }
+
+    view! {
+        <div
+            id=format!("post-{post_num}")
+            class=move || {
+                if list.focused.get() == Some(post_num) {
+                    "rounded-lg bg-neutral-800 transition-colors"
+                } else {
+                    "rounded-lg transition-colors"
+                }
             }
         >
+            <Message
+                username=&post.user
+                message=&post.message
+                time=&post.time
+                edited=post.edited_at.is_some()
+                deleted=post.deleted
+                attachments=post.attachments.clone()
+                me=me
+            >
+                <Show when=move || !deleted && me.get() == author fallback=move || view! {}>
+                    <PostControls post_num=post_num message=message.clone() list=list/>
+                </Show>
+                <Show when=move || !deleted fallback=move || view! {}>
+                    <ReactionBar post_num=post_num reactions=reactions.clone() me=me list=list/>
+                </Show>
+                {has_thread.then(|| view! { <Replies post_num=post_num me=me list=list/> })}
+            </Message>
+        </div>
+    }
+    .into_view()
+}
+
+//The reply count under a post, which opens up into the replies and a box to add one
+#[component]
+fn Replies(post_num: u64, me: ReadSignal<String>, list: PostList) -> impl IntoView {
+    let open = create_memo(move |_| list.open_threads.get().contains(&post_num));
+    //Read from the list rather than passed in, so a new reply doesn't redraw the whole post
+    let reply_count = create_memo(move |_| {
+        list.messages.with(|posts| {
+            posts
+                .iter()
+                .find(|post| post.post_num == post_num)
+                .map_or(0, |post| post.reply_count)
+        })
+    });
+    let draft = create_rw_signal(String::new());
+    let status = create_rw_signal(String::new());
+
+    //The replies go in with every other post, so live updates reach them the same way
+    let load_fn = move || {
+        spawn_local(async move {
+            match Thread::fetch(post_num).await {
+                Ok(thread) => {
+                    status.set(String::new());
+
+                    list.messages.update(|posts| {
+                        merge_posts(posts, vec![thread.post]);
+                        merge_posts(posts, thread.replies);
+                    });
+                }
+                Err(e) => status.set(e.to_string()),
+            }
+        });
+    };
+
+    //Opened before it was drawn, by a jump to one of its replies
+    if open.get_untracked() {
+        load_fn();
+    }
+
+    let toggle_fn = move || {
+        if open.get_untracked() {
+            list.open_threads
+                .update(|threads| threads.retain(|&thread| thread != post_num));
+        } else {
+            list.open_threads.update(|threads| threads.push(post_num));
+            load_fn();
+        }
+    };
+
+    let reply_fn = move || {
+        let message = draft.get_untracked();
 
-            <p>{"^ Click me to load messages!"}</p>
+        if message.trim().is_empty() {
+            return;
+        }
+
+        spawn_local(async move {
+            match crate::utils::posts::reply(post_num, message).await {
+                Ok(_) => {
+                    draft.set(String::new());
+                    //Picks up the new reply count along with the reply
+                    load_fn();
+                }
+                Err(e) => status.set(e.to_string()),
+            }
+        });
+    };
+
+    view! {
+        <button class="w-fit text-xs text-gray-500 hover:text-white" on:click=move |_| toggle_fn()>
+            {move || match (open.get(), reply_count.get()) {
+                (true, _) => "Hide replies".to_string(),
+                (false, 0) => "Reply".to_string(),
+                (false, 1) => "1 reply".to_string(),
+                (false, n) => format!("{n} replies"),
+            }}
+
+        </button>
+        <Show when=move || open.get() fallback=move || view! {}>
+            <div class="space-y-2 border-l border-neutral-700 pl-3">
+                <For
+                    each=move || {
+                        list.messages
+                            .get()
+                            .into_iter()
+                            .filter(|post| post.parent_post_num == Some(post_num))
+                            .collect::<Vec<_>>()
+                    }
+                    key=post_key
+                    children=move |post| post_view(post, me, list)
+                />
+                <div class="flex gap-2 text-xs">
+                    <textarea
+                        class="flex-1 rounded-lg bg-neutral-800 p-1"
+                        placeholder="Reply..."
+                        prop:value=move || draft.get()
+                        on:input=move |ev| draft.set(event_target_value(&ev))
+                    ></textarea>
+                    <button class="hover:text-white" on:click=move |_| reply_fn()>
+                        Send
+                    </button>
+                </div>
+            </div>
         </Show>
+        <p class="text-xs text-red-500">{status}</p>
+    }
+}
 
-        <Show
-            when=move || { result.get().is_empty() }
-            fallback=move || {
-                view! { <h1 class="text-red">{format!("Error: {}", result.get())}</h1> }
+#[component]
+fn ReactionBar(
+    post_num: u64,
+    reactions: Vec<Reaction>,
+    me: ReadSignal<String>,
+    list: PostList,
+) -> impl IntoView {
+    let picking = create_rw_signal(false);
+    let status = create_rw_signal(String::new());
+
+    //Clicking a reaction of our own takes it back
+    let react_fn = move |emoji: String, remove: bool| {
+        picking.set(false);
+
+        spawn_local(async move {
+            match crate::utils::posts::react(post_num, &emoji, remove).await {
+                Ok(v) => {
+                    status.set(String::new());
+
+                    if let Ok(post) = serde_json::from_str::<Post>(&v) {
+                        list.messages.update(|posts| merge_posts(posts, vec![post]));
+                    }
+                }
+                Err(e) => status.set(e.to_string()),
             }
-        >
+        });
+    };
 
-            {move || {
-                messages
-                    .get()
+    view! {
+        <div class="flex flex-wrap items-center gap-1 text-xs">
+            {reactions
+                .into_iter()
+                .map(|reaction| {
+                    let emoji = reaction.emoji.clone();
+                    let title = reaction.users.join(", ");
+                    let users = reaction.users;
+                    let mine = create_memo(move |_| users.contains(&me.get()));
+                    view! {
+                        <button
+                            class=move || {
+                                if mine.get() {
+                                    "rounded-full border border-blue-500 bg-neutral-800 px-2"
+                                } else {
+                                    "rounded-full border border-neutral-700 px-2"
+                                }
+                            }
+                            title=title
+                            on:click=move |_| react_fn(emoji.clone(), mine.get_untracked())
+                        >
+                            {reaction.emoji}
+                            " "
+                            {reaction.count}
+                        </button>
+                    }
+                })
+                .collect_view()}
+            <button
+                class="rounded-full px-2 text-gray-500 hover:text-white"
+                on:click=move |_| picking.update(|picking| *picking = !*picking)
+            >
+                "+"
+            </button>
+            <Show when=move || picking.get() fallback=move || view! {}>
+                {QUICK_REACTIONS
                     .iter()
-                    .map(|post| {
+                    .map(|emoji| {
                         view! {
-                            <Message username=&post.user message=&post.message time=&post.time/>
+                            <button
+                                class="px-1 hover:scale-125"
+                                on:click=move |_| react_fn(emoji.to_string(), false)
+                            >
+                                {*emoji}
+                            </button>
                         }
                     })
-                    .collect_view()
-            }}
+                    .collect_view()}
+            </Show>
+            <span class="text-red-500">{status}</span>
+        </div>
+    }
+}
+
+#[component]
+fn PostControls(post_num: u64, message: String, list: PostList) -> impl IntoView {
+    let editing = create_rw_signal(false);
+    let draft = create_rw_signal(message);
+    let status = create_rw_signal(String::new());
 
+    let save_fn = move || {
+        spawn_local(async move {
+            match crate::utils::posts::edit_post(post_num, draft.get_untracked()).await {
+                Ok(v) => {
+                    editing.set(false);
+
+                    if let Ok(post) = serde_json::from_str::<Post>(&v) {
+                        list.messages.update(|posts| merge_posts(posts, vec![post]));
+                    }
+                }
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    let delete_fn = move || {
+        if !window()
+            .confirm_with_message("Delete this message?")
+            .unwrap_or(false)
+        {
+            return;
+        }
+
+        spawn_local(async move {
+            match crate::utils::posts::delete_post(post_num).await {
+                Ok(v) => {
+                    if let Ok(post) = serde_json::from_str::<Post>(&v) {
+                        list.messages.update(|posts| merge_posts(posts, vec![post]));
+                    }
+                }
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    view! {
+        <Show
+            when=move || editing.get()
+            fallback=move || {
+                view! {
+                    <div class="flex gap-2 text-xs text-gray-500">
+                        <button class="hover:text-white" on:click=move |_| editing.set(true)>
+                            Edit
+                        </button>
+                        <button class="hover:text-white" on:click=move |_| delete_fn()>
+                            Delete
+                        </button>
+                    </div>
+                }
+            }
+        >
+
+            <div class="flex gap-2 text-xs">
+                <textarea
+                    class="flex-1 rounded-lg bg-neutral-800 p-1"
+                    prop:value=move || draft.get()
+                    on:input=move |ev| draft.set(event_target_value(&ev))
+                ></textarea>
+                <button class="hover:text-white" on:click=move |_| save_fn()>
+                    Save
+                </button>
+                <button class="hover:text-white" on:click=move |_| editing.set(false)>
+                    Cancel
+                </button>
+            </div>
         </Show>
+        <p class="text-xs text-red-500">{status}</p>
     }
 }
 
 #[component]
-fn Message<'a>(username: &'a str, message: &'a str, time: &'a str) -> impl IntoView {
+pub(super) fn Message<'a>(
+    username: &'a str,
+    message: &'a str,
+    time: &'a str,
+    #[prop(optional)] edited: bool,
+    #[prop(optional)] deleted: bool,
+    #[prop(optional)] attachments: Vec<Attachment>,
+    //Who is reading, so mentions of them stand out
+    #[prop(optional)] me: Option<ReadSignal<String>>,
+    #[prop(optional)] children: Option<Children>,
+) -> impl IntoView {
     view! {
         <div class="flex items-start space-x-2">
             <div class="grid gap-1 text-sm">
                 <div class="font-semibold">{username.to_string()} :</div>
-                <div class="text-sm">{message.to_string()}</div>
-                <div class="text-xs text-gray-500 dark:text-gray-400">- {time.to_string()}</div>
+                {if deleted {
+                    view! {
+                        <div class="text-sm">
+                            <i class="text-gray-500">This message was deleted</i>
+                        </div>
+                    }
+                } else {
+                    let message = message.to_string();
+                    //render escapes everything it doesn't turn into markup itself. Redrawn once we
+                    //know who we are
+                    view! {
+                        <div>
+                            {move || {
+                                let me = me.map(|me| me.get()).unwrap_or_default();
+                                view! {
+                                    <div
+                                        class="markdown text-sm space-y-1"
+                                        inner_html=crate::utils::markdown::render(&message, &me)
+                                    ></div>
+                                }
+                            }}
+
+                        </div>
+                    }
+                }}
+                <div class="flex flex-wrap items-end gap-2">
+                    {attachments.into_iter().map(attachment_view).collect_view()}
+                </div>
+                <div
+                    class="text-xs text-gray-500 dark:text-gray-400"
+                    title=crate::utils::time::local_time(time)
+                >
+                    - {crate::utils::time::relative_time(time)} {edited.then_some(" (edited)")}
+                </div>
+                {children.map(|children| children())}
             </div>
         </div>
     }
 }
+
+//Images are previewed, anything else is a download link
+fn attachment_view(attachment: Attachment) -> impl IntoView {
+    let url = format!("attachments/{}", attachment.attachment_num);
+
+    if attachment.mime.starts_with("image/") {
+        view! {
+            <a
+                href=url.clone()
+                target="_blank"
+                rel="noopener noreferrer"
+                title=attachment.name.clone()
+            >
+                <img src=url alt=attachment.name loading="lazy" class="max-h-48 max-w-xs rounded-lg"/>
+            </a>
+        }
+        .into_view()
+    } else {
+        view! {
+            <a href=url download=attachment.name.clone() class="text-xs underline">
+                {attachment.name}
+                " ("
+                {format_size(attachment.size)}
+                ")"
+            </a>
+        }
+        .into_view()
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
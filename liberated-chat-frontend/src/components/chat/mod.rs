@@ -1,29 +1,257 @@
-use leptos::{component, view, IntoView};
+use crate::utils::posts::Cursor;
+use leptos::{
+    component, create_memo, create_rw_signal, spawn_local, view, IntoView, SignalGet, SignalSet,
+};
 use serde::Deserialize;
 
+mod dm;
 mod footer;
 mod message;
+mod rooms;
+mod search;
+mod sessions;
+
+//Room everyone is in, same as the server's
+const GENERAL_ROOM: u64 = 1;
+
+//What the chat window is currently showing and where new messages get sent
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Room(u64),
+    Direct(String),
+}
 
 #[derive(Deserialize, Debug, Clone)]
 struct Post {
-    // Not used yet!
-    // _post_num: u64,
+    post_num: u64,
+    room: u64,
     user: String,
     message: String,
     time: String,
+    edited_at: Option<String>,
+    deleted: bool,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    reactions: Vec<Reaction>,
+    //Set on replies, to the post that started the thread
+    #[serde(default)]
+    parent_post_num: Option<u64>,
+    #[serde(default)]
+    reply_count: u64,
 }
 
+//A post and every reply to it
+#[derive(Deserialize, Debug, Clone)]
+struct Thread {
+    post: Post,
+    replies: Vec<Post>,
+}
+
+//Everyone who reacted to a post with the same emoji
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+struct Reaction {
+    emoji: String,
+    count: u64,
+    users: Vec<String>,
+}
+
+//A file on a post, served from attachments/<attachment_num>
+#[derive(Deserialize, Debug, Clone)]
+struct Attachment {
+    attachment_num: u64,
+    name: String,
+    mime: String,
+    size: u64,
+}
+
+//How many posts to ask the server for at once
+const PAGE_SIZE: u64 = 50;
+
 impl Post {
-    async fn new() -> Result<Vec<Post>, Box<dyn std::error::Error>> {
-        let posts_string = crate::utils::posts::get_posts().await?;
+    async fn fetch(room: u64, cursor: Cursor) -> Result<Vec<Post>, Box<dyn std::error::Error>> {
+        let posts_string = crate::utils::posts::get_posts(room, cursor, PAGE_SIZE).await?;
         Ok(serde_json::from_str(&posts_string)?)
     }
 }
 
+impl Thread {
+    async fn fetch(post_num: u64) -> Result<Thread, Box<dyn std::error::Error>> {
+        let thread_string = crate::utils::posts::get_thread(post_num).await?;
+        Ok(serde_json::from_str(&thread_string)?)
+    }
+}
+
+//A post matching a search, with the words that matched marked in the snippet
+#[derive(Deserialize, Debug, Clone)]
+struct SearchResult {
+    post: Post,
+    snippet: Vec<SnippetPart>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct SnippetPart {
+    text: String,
+    highlight: bool,
+}
+
+impl SearchResult {
+    async fn search(
+        query: &str,
+        user: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error>> {
+        let results_string = crate::utils::search::search(query, user, from, to).await?;
+        Ok(serde_json::from_str(&results_string)?)
+    }
+}
+
+//A post to bring into view, picked up by the room's message list once it is showing
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Jump {
+    room: u64,
+    post_num: u64,
+    //Set for replies, whose thread gets opened
+    parent_post_num: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Room {
+    room_id: u64,
+    name: String,
+    joined: bool,
+}
+
+impl Room {
+    async fn list() -> Result<Vec<Room>, Box<dyn std::error::Error>> {
+        let rooms_string = crate::utils::rooms::get_rooms().await?;
+        Ok(serde_json::from_str(&rooms_string)?)
+    }
+}
+
+//One device the user is logged in on
+#[derive(Deserialize, Debug, Clone)]
+struct Session {
+    session_num: u64,
+    user_agent: String,
+    ip: String,
+    current: bool,
+}
+
+impl Session {
+    async fn list() -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+        let sessions_string = crate::utils::sessions::get_sessions().await?;
+        Ok(serde_json::from_str(&sessions_string)?)
+    }
+}
+
+//Only the limits the client checks itself, see GET /limits
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct Limits {
+    max_message_length: usize,
+}
+
+impl Limits {
+    async fn get() -> Result<Limits, Box<dyn std::error::Error>> {
+        let limits_string = crate::utils::posts::get_limits().await?;
+        Ok(serde_json::from_str(&limits_string)?)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct DirectMessage {
+    dm_num: u64,
+    from: String,
+    message: String,
+    time: String,
+}
+
+impl DirectMessage {
+    async fn fetch(
+        username: &str,
+        cursor: Cursor,
+    ) -> Result<Vec<DirectMessage>, Box<dyn std::error::Error>> {
+        let messages_string =
+            crate::utils::dm::get_direct_messages(username, cursor, PAGE_SIZE).await?;
+        Ok(serde_json::from_str(&messages_string)?)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Conversation {
+    user: String,
+    last_message: DirectMessage,
+}
+
+impl Conversation {
+    async fn list() -> Result<Vec<Conversation>, Box<dyn std::error::Error>> {
+        let conversations_string = crate::utils::dm::get_conversations().await?;
+        Ok(serde_json::from_str(&conversations_string)?)
+    }
+}
+
+//Adds posts to an already loaded list, keeping it in order. Posts already there are
+//replaced, which is how edits and deletes show up
+fn merge_posts(posts: &mut Vec<Post>, new: Vec<Post>) {
+    for post in new {
+        match posts.binary_search_by_key(&post.post_num, |p| p.post_num) {
+            Ok(i) => posts[i] = post,
+            Err(i) => posts.insert(i, post),
+        }
+    }
+}
+
 #[component]
 pub fn Chat() -> impl IntoView {
+    let target = create_rw_signal(Target::Room(GENERAL_ROOM));
+    //Bumped every time the footer sends something, so views without a live feed can refresh
+    let sent = create_rw_signal(0u64);
+
+    //Only rebuild the message view when the target actually changes
+    let current = create_memo(move |_| target.get());
+
+    //Who we are logged in as, so our own posts can be told apart
+    let me = create_rw_signal(String::new());
+    //Set by search results
+    let jump = create_rw_signal(None::<Jump>);
+
+    spawn_local(async move {
+        if let Ok(v) = crate::utils::auth::whoami().await {
+            me.set(v);
+        }
+    });
+
     view! {
-        <message::Messages></message::Messages>
-        <footer::Footer></footer::Footer>
+        <div class="flex">
+            <div class="w-48 shrink-0 border-r h-[calc(100vh-3.5rem)] overflow-y-auto">
+                <search::Search target=target jump=jump></search::Search>
+                <rooms::RoomList target=target></rooms::RoomList>
+                <dm::Conversations target=target sent=sent.read_only()></dm::Conversations>
+                <sessions::Sessions></sessions::Sessions>
+            </div>
+            <div class="flex-1 min-w-0">
+                {move || match current.get() {
+                    Target::Room(room) => {
+                        view! {
+                            <message::Messages
+                                room=room
+                                me=me.read_only()
+                                jump=jump
+                            ></message::Messages>
+                        }
+                            .into_view()
+                    }
+                    Target::Direct(user) => {
+                        view! {
+                            <dm::DirectMessages user=user sent=sent.read_only()></dm::DirectMessages>
+                        }
+                            .into_view()
+                    }
+                }}
+
+                <footer::Footer target=target.read_only() sent=sent></footer::Footer>
+            </div>
+        </div>
     }
 }
@@ -0,0 +1,116 @@
+use leptos::{
+    component, create_rw_signal, event_target_value, spawn_local, view, CollectView, IntoView,
+    RwSignal, SignalGet, SignalGetUntracked, SignalSet,
+};
+
+#[component]
+pub fn RoomList(target: RwSignal<super::Target>) -> impl IntoView {
+    let rooms = create_rw_signal(Vec::<super::Room>::new());
+    let new_room = create_rw_signal(String::new());
+    let status = create_rw_signal(String::new());
+
+    let load_rooms_fn = move || {
+        spawn_local(async move {
+            match super::Room::list().await {
+                Ok(v) => rooms.set(v),
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    let create_fn = move || {
+        spawn_local(async move {
+            match crate::utils::rooms::create_room(new_room.get_untracked()).await {
+                Ok(v) => {
+                    status.set(String::new());
+
+                    if let Ok(created) = serde_json::from_str::<super::Room>(&v) {
+                        target.set(super::Target::Room(created.room_id));
+                    }
+
+                    load_rooms_fn();
+                }
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    let join_fn = move |room_id: u64| {
+        spawn_local(async move {
+            match crate::utils::rooms::join_room(room_id).await {
+                Ok(_) => {
+                    status.set(String::new());
+                    target.set(super::Target::Room(room_id));
+                    load_rooms_fn();
+                }
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    load_rooms_fn();
+
+    view! {
+        <div class="p-2 space-y-2">
+            <h2 class="font-semibold">Rooms</h2>
+            {move || {
+                rooms
+                    .get()
+                    .into_iter()
+                    .map(|r| {
+                        let room_id = r.room_id;
+                        if r.joined {
+                            view! {
+                                <button
+                                    class=move || {
+                                        if target.get() == super::Target::Room(room_id) {
+                                            "block w-full text-left rounded-md px-2 py-1 text-sm bg-neutral-800"
+                                        } else {
+                                            "block w-full text-left rounded-md px-2 py-1 text-sm hover:bg-neutral-800"
+                                        }
+                                    }
+
+                                    on:click=move |_| target.set(super::Target::Room(room_id))
+                                >
+                                    "# "
+                                    {r.name}
+                                </button>
+                            }
+                        } else {
+                            view! {
+                                <button
+                                    class="block w-full text-left rounded-md px-2 py-1 text-sm text-gray-500 hover:bg-neutral-800"
+                                    on:click=move |_| join_fn(room_id)
+                                >
+                                    "# "
+                                    {r.name}
+                                    " (join)"
+                                </button>
+                            }
+                        }
+                    })
+                    .collect_view()
+            }}
+
+            <div class="flex rounded-lg border">
+                <input
+                    class="min-w-0 flex-1 rounded-l-lg bg-neutral-800 p-1 text-sm"
+                    placeholder="New room"
+                    on:change=move |ev| {
+                        new_room.set(event_target_value(&ev));
+                    }
+                />
+                <button
+                    class="rounded-r-lg bg-neutral-900 px-2 text-sm"
+                    on:click=move |_| {
+                        create_fn();
+                    }
+                >
+
+                    Create
+                </button>
+            </div>
+            <p class="text-xs">{status}</p>
+        </div>
+    }
+}
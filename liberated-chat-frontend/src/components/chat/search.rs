@@ -0,0 +1,149 @@
+use leptos::{
+    component, create_rw_signal, ev, event_target_value, spawn_local, view, CollectView, IntoView,
+    RwSignal, Show, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate,
+};
+
+#[component]
+pub fn Search(
+    target: RwSignal<super::Target>,
+    jump: RwSignal<Option<super::Jump>>,
+) -> impl IntoView {
+    let query = create_rw_signal(String::new());
+    let user = create_rw_signal(String::new());
+    let from = create_rw_signal(String::new());
+    let to = create_rw_signal(String::new());
+    //The author and date filters are tucked away until asked for
+    let filters = create_rw_signal(false);
+    let results = create_rw_signal(Vec::<super::SearchResult>::new());
+    let status = create_rw_signal(String::new());
+
+    let search_fn = move || {
+        if query.get_untracked().trim().is_empty() {
+            results.set(Vec::new());
+            status.set(String::new());
+            return;
+        }
+
+        spawn_local(async move {
+            match super::SearchResult::search(
+                &query.get_untracked(),
+                &user.get_untracked(),
+                &from.get_untracked(),
+                &to.get_untracked(),
+            )
+            .await
+            {
+                Ok(v) => {
+                    status.set(if v.is_empty() {
+                        "No matches".into()
+                    } else {
+                        String::new()
+                    });
+                    results.set(v);
+                }
+                Err(e) => status.set(e.to_string()),
+            }
+        });
+    };
+
+    //The room is switched to first, its message list then picks up the jump
+    let open_fn = move |to_post: super::Jump| {
+        target.set(super::Target::Room(to_post.room));
+        jump.set(Some(to_post));
+    };
+
+    view! {
+        <div class="p-2 space-y-2">
+            <h2 class="font-semibold">Search</h2>
+            <div class="flex rounded-lg border">
+                <input
+                    class="min-w-0 flex-1 rounded-l-lg bg-neutral-800 p-1 text-sm"
+                    placeholder="Search messages"
+                    prop:value=move || query.get()
+                    on:input=move |ev| query.set(event_target_value(&ev))
+                    on:keydown=move |ev: ev::KeyboardEvent| {
+                        if ev.key() == "Enter" {
+                            search_fn();
+                        }
+                    }
+                />
+
+                <button
+                    class="rounded-r-lg bg-neutral-900 px-2 text-sm"
+                    on:click=move |_| search_fn()
+                >
+                    Go
+                </button>
+            </div>
+            <button
+                class="text-xs text-gray-500 hover:text-white"
+                on:click=move |_| filters.update(|filters| *filters = !*filters)
+            >
+                Filters
+            </button>
+            <Show when=move || filters.get() fallback=move || view! {}>
+                <input
+                    class="w-full rounded-lg bg-neutral-800 p-1 text-xs"
+                    placeholder="From user"
+                    prop:value=move || user.get()
+                    on:input=move |ev| user.set(event_target_value(&ev))
+                />
+                <input
+                    type="date"
+                    class="w-full rounded-lg bg-neutral-800 p-1 text-xs"
+                    title="Posted on or after"
+                    prop:value=move || from.get()
+                    on:input=move |ev| from.set(event_target_value(&ev))
+                />
+                <input
+                    type="date"
+                    class="w-full rounded-lg bg-neutral-800 p-1 text-xs"
+                    title="Posted on or before"
+                    prop:value=move || to.get()
+                    on:input=move |ev| to.set(event_target_value(&ev))
+                />
+            </Show>
+            {move || {
+                results
+                    .get()
+                    .into_iter()
+                    .map(|result| {
+                        let to_post = super::Jump {
+                            room: result.post.room,
+                            post_num: result.post.post_num,
+                            parent_post_num: result.post.parent_post_num,
+                        };
+                        view! {
+                            <button
+                                class="block w-full text-left rounded-md px-2 py-1 hover:bg-neutral-800"
+                                on:click=move |_| open_fn(to_post)
+                            >
+                                <div class="text-xs font-semibold">{result.post.user}</div>
+                                <div class="text-xs text-gray-400 break-words">
+                                    {result
+                                        .snippet
+                                        .into_iter()
+                                        .map(|part| {
+                                            if part.highlight {
+                                                view! {
+                                                    <mark class="bg-yellow-600 text-white">
+                                                        {part.text}
+                                                    </mark>
+                                                }
+                                                    .into_view()
+                                            } else {
+                                                part.text.into_view()
+                                            }
+                                        })
+                                        .collect_view()}
+                                </div>
+                            </button>
+                        }
+                    })
+                    .collect_view()
+            }}
+
+            <p class="text-xs">{status}</p>
+        </div>
+    }
+}
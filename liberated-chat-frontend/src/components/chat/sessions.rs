@@ -0,0 +1,89 @@
+use leptos::{
+    component, create_rw_signal, spawn_local, view, CollectView, IntoView, SignalGet, SignalSet,
+};
+
+#[component]
+pub fn Sessions() -> impl IntoView {
+    let sessions = create_rw_signal(Vec::<super::Session>::new());
+    let status = create_rw_signal(String::new());
+
+    let load_sessions_fn = move || {
+        spawn_local(async move {
+            match super::Session::list().await {
+                Ok(v) => sessions.set(v),
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    let revoke_fn = move |session_num: u64| {
+        spawn_local(async move {
+            match crate::utils::sessions::revoke_session(session_num).await {
+                Ok(_) => load_sessions_fn(),
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    let revoke_others_fn = move || {
+        spawn_local(async move {
+            match crate::utils::sessions::revoke_other_sessions().await {
+                Ok(_) => load_sessions_fn(),
+                Err(e) => status.set(format!("{e:?}")),
+            }
+        });
+    };
+
+    load_sessions_fn();
+
+    view! {
+        <div class="p-2 space-y-2">
+            <h2 class="font-semibold">Devices</h2>
+            {move || {
+                sessions
+                    .get()
+                    .into_iter()
+                    .map(|session| {
+                        let session_num = session.session_num;
+                        view! {
+                            <div class="rounded-md px-2 py-1 text-sm">
+                                <div class="truncate" title=session.user_agent.clone()>
+                                    {if session.user_agent.is_empty() {
+                                        "Unknown device".to_string()
+                                    } else {
+                                        session.user_agent
+                                    }}
+                                </div>
+                                <div class="flex text-xs text-gray-500">
+                                    <span class="flex-1">{session.ip}</span>
+                                    {if session.current {
+                                        view! { <span>"This device"</span> }.into_view()
+                                    } else {
+                                        view! {
+                                            <button
+                                                class="hover:text-red-500"
+                                                on:click=move |_| revoke_fn(session_num)
+                                            >
+                                                "Log out"
+                                            </button>
+                                        }
+                                            .into_view()
+                                    }}
+
+                                </div>
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            }}
+
+            <button
+                class="w-full rounded-md border px-2 py-1 text-sm hover:bg-neutral-800"
+                on:click=move |_| revoke_others_fn()
+            >
+                "Log out other devices"
+            </button>
+            <p class="text-xs">{status}</p>
+        </div>
+    }
+}
@@ -8,6 +8,9 @@ pub fn LoginPage() -> impl IntoView {
     let (username, set_username) = create_signal(String::new());
     let (password, set_password) = create_signal(String::new());
     let (login_result, set_login_result) = create_signal(String::new());
+    //Shown under the field the server rejected when registering
+    let (username_error, set_username_error) = create_signal(String::new());
+    let (password_error, set_password_error) = create_signal(String::new());
 
     let login_fn = move || {
         spawn_local(async move {
@@ -32,7 +35,15 @@ pub fn LoginPage() -> impl IntoView {
 
             match res {
                 Ok(v) => set_login_result.set(v),
-                Err(e) => set_login_result.set(e.to_string()),
+                Err(e) => match e.downcast_ref::<crate::utils::ApiError>() {
+                    Some(e) if e.code == "invalid_username" || e.code == "username_taken" => {
+                        set_username_error.set(e.to_string())
+                    }
+                    Some(e) if e.code == "invalid_password" => {
+                        set_password_error.set(e.to_string())
+                    }
+                    _ => set_login_result.set(e.to_string()),
+                },
             }
         });
     };
@@ -70,6 +81,7 @@ pub fn LoginPage() -> impl IntoView {
                             data-lastpass-icon-root=""
                             style="position: relative !important; height: 0px !important; width: 0px !important; float: left !important;"
                         ></div>
+                        <p class="text-xs text-red-500">{username_error}</p>
                     </div>
                     <div class="space-y-2 text-center">
                         <label
@@ -93,6 +105,7 @@ pub fn LoginPage() -> impl IntoView {
                             data-lastpass-icon-root=""
                             style="position: relative !important; height: 0px !important; width: 0px !important; float: left !important;"
                         ></div>
+                        <p class="text-xs text-red-500">{password_error}</p>
                     </div>
                 </div>
                 <div class="items-center p-2 flex justify-center">
@@ -100,6 +113,8 @@ pub fn LoginPage() -> impl IntoView {
                         class="bg-neutral-800 inline-flex items-center justify-center whitespace-nowrap rounded-md text-sm font-medium ring-offset-background transition-colors focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring focus-visible:ring-offset-2 disabled:pointer-events-none disabled:opacity-50 bg-primary text-primary-foreground hover:bg-primary/90 h-10 px-4 py-2 w-10/12"
                         on:click=move |_| {
                             set_login_result.set(String::new());
+                            set_username_error.set(String::new());
+                            set_password_error.set(String::new());
                             login_fn();
                         }
                     >
@@ -113,6 +128,8 @@ pub fn LoginPage() -> impl IntoView {
                         class="bg-neutral-800 inline-flex items-center justify-center whitespace-nowrap rounded-md text-sm font-medium ring-offset-background transition-colors focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring focus-visible:ring-offset-2 disabled:pointer-events-none disabled:opacity-50 bg-primary text-primary-foreground hover:bg-primary/90 h-10 px-4 py-2 w-10/12"
                         on:click=move |_| {
                             set_login_result.set(String::new());
+                            set_username_error.set(String::new());
+                            set_password_error.set(String::new());
                             register_fn();
                         }
                     >
@@ -1,7 +1,46 @@
 use leptos::{
-    component, create_signal, spawn_local, view, IntoView, SignalGet, SignalSet, SignalUpdate,
-    WriteSignal,
+    component, create_rw_signal, create_signal, on_cleanup, set_interval_with_handle, spawn_local,
+    view, CollectView, IntoView, Show, SignalGet, SignalSet, SignalUpdate, SignalWith, WriteSignal,
 };
+use serde::Deserialize;
+use std::time::Duration;
+
+//How often the unread count is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+//Just what the inbox shows of the post someone was mentioned in
+#[derive(Deserialize, Debug, Clone)]
+struct MentionPost {
+    user: String,
+    message: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Notification {
+    mention_num: u64,
+    read: bool,
+    post: MentionPost,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+struct Notifications {
+    unread: u64,
+    notifications: Vec<Notification>,
+}
+
+impl Notifications {
+    async fn fetch() -> Result<Notifications, Box<dyn std::error::Error>> {
+        let notifications_string = crate::utils::notifications::get_notifications().await?;
+        Ok(serde_json::from_str(&notifications_string)?)
+    }
+
+    async fn mark_read(
+        mention_num: Option<u64>,
+    ) -> Result<Notifications, Box<dyn std::error::Error>> {
+        let notifications_string = crate::utils::notifications::mark_read(mention_num).await?;
+        Ok(serde_json::from_str(&notifications_string)?)
+    }
+}
 
 #[component]
 pub fn NavBar(toggle_login: WriteSignal<bool>) -> impl IntoView {
@@ -19,10 +58,11 @@ pub fn NavBar(toggle_login: WriteSignal<bool>) -> impl IntoView {
                 <span>Liberated Chat</span>
                 <Icon/>
                 <p>
-                    <i>Times all in UTC, site subject to change, no illegal stuff please.</i>
+                    <i>Site subject to change, no illegal stuff please.</i>
                 </p>
             </div>
             <div class="ml-auto flex items-center gap-4">
+                <Inbox/>
                 <p class="inline-flex items-center justify-center whitespace-nowrap text-sm font-medium ring-offset-background transition-colors">
                     {move || {
                         if clickme_status.get() {
@@ -86,6 +126,107 @@ pub fn NavBar(toggle_login: WriteSignal<bool>) -> impl IntoView {
     }
 }
 
+//Bell with the unread count, opening up into the posts we were mentioned in
+#[component]
+fn Inbox() -> impl IntoView {
+    let notifications = create_rw_signal(Notifications::default());
+    let open = create_rw_signal(false);
+
+    //Fails whenever nobody is logged in, which just leaves the inbox empty
+    let load_fn = move || {
+        spawn_local(async move {
+            if let Ok(v) = Notifications::fetch().await {
+                notifications.set(v);
+            }
+        });
+    };
+
+    let read_fn = move |mention_num: Option<u64>| {
+        spawn_local(async move {
+            if let Ok(v) = Notifications::mark_read(mention_num).await {
+                notifications.set(v);
+            }
+        });
+    };
+
+    load_fn();
+
+    if let Ok(handle) = set_interval_with_handle(load_fn, POLL_INTERVAL) {
+        on_cleanup(move || handle.clear());
+    }
+
+    view! {
+        <div class="relative">
+            <button
+                class="relative inline-flex items-center justify-center whitespace-nowrap text-sm font-medium ring-offset-background transition-colors border border-input bg-background hover:bg-accent hover:text-accent-foreground h-9 rounded-md px-3"
+                title="Mentions"
+                on:click=move |_| {
+                    if !open.get() {
+                        load_fn();
+                    }
+                    open.update(|open| *open = !*open);
+                }
+            >
+
+                "🔔"
+                <Show
+                    when=move || notifications.with(|notifications| notifications.unread > 0)
+                    fallback=move || view! {}
+                >
+                    <span class="absolute -top-2 -right-2 rounded-full bg-red-600 px-1.5 text-xs text-white">
+                        {move || notifications.with(|notifications| notifications.unread)}
+                    </span>
+                </Show>
+            </button>
+            <Show when=move || open.get() fallback=move || view! {}>
+                <div class="absolute right-0 z-10 mt-2 w-80 max-h-96 overflow-y-auto rounded-md border bg-neutral-900 p-2 space-y-1 text-sm">
+                    <div class="flex items-center justify-between">
+                        <span class="font-semibold">Mentions</span>
+                        <button
+                            class="text-xs text-gray-500 hover:text-white"
+                            on:click=move |_| read_fn(None)
+                        >
+                            Mark all read
+                        </button>
+                    </div>
+                    {move || {
+                        let notifications = notifications.get().notifications;
+                        if notifications.is_empty() {
+                            return view! {
+                                <p class="text-xs text-gray-500">Nobody has mentioned you yet</p>
+                            }
+                                .into_view();
+                        }
+                        notifications
+                            .into_iter()
+                            .map(|notification| {
+                                let mention_num = notification.mention_num;
+                                view! {
+                                    <button
+                                        class=if notification.read {
+                                            "block w-full text-left rounded-md px-2 py-1 text-gray-500 hover:bg-neutral-800"
+                                        } else {
+                                            "block w-full text-left rounded-md px-2 py-1 font-semibold hover:bg-neutral-800"
+                                        }
+
+                                        on:click=move |_| read_fn(Some(mention_num))
+                                    >
+                                        <div>{notification.post.user} " mentioned you"</div>
+                                        <div class="text-xs truncate">
+                                            {notification.post.message}
+                                        </div>
+                                    </button>
+                                }
+                            })
+                            .collect_view()
+                    }}
+
+                </div>
+            </Show>
+        </div>
+    }
+}
+
 #[component]
 fn Icon() -> impl IntoView {
     view! {
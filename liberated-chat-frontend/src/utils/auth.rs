@@ -1,17 +1,34 @@
-use reqwest::StatusCode;
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::Serialize;
 use std::path::Path;
 
-pub async fn login(username: &str, password: &str) -> Result<String, Box<dyn std::error::Error>> {
+#[derive(Serialize)]
+struct Credentials<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+async fn send_credentials(
+    route: &str,
+    username: &str,
+    password: &str,
+) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
     let base_url = super::get_base_url().expect("Failed to get base url!");
     let path = Path::new(&base_url);
 
+    let body = serde_json::to_string(&Credentials { username, password })?;
+
     let client = reqwest::Client::new();
-    let req = client
-        .post(path.join("login").to_str().unwrap())
-        .header("Username", username)
-        .header("Password", password)
+    Ok(client
+        .post(path.join(route).to_str().unwrap())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
         .send()
-        .await?;
+        .await?)
+}
+
+pub async fn login(username: &str, password: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let req = send_credentials("login", username, password).await?;
 
     match req.status() {
         StatusCode::OK => {
@@ -23,11 +40,7 @@ pub async fn login(username: &str, password: &str) -> Result<String, Box<dyn std
                 Ok(text)
             }
         }
-        StatusCode::INTERNAL_SERVER_ERROR => Err("Internal service error!".into()),
-        StatusCode::BAD_REQUEST => Err("Not all inputs provided!".into()),
-        StatusCode::UNAUTHORIZED => Err("No/invalid login!".into()),
-        StatusCode::CONFLICT => Err("No user exists!".into()),
-        e => Err(format!("{e:?}").into()),
+        _ => Err(super::error_message(req).await.into()),
     }
 }
 
@@ -35,16 +48,7 @@ pub async fn register(
     username: &str,
     password: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let base_url = super::get_base_url().expect("Failed to get base url!");
-    let path = Path::new(&base_url);
-
-    let client = reqwest::Client::new();
-    let req = client
-        .post(path.join("register").to_str().unwrap())
-        .header("Username", username)
-        .header("Password", password)
-        .send()
-        .await?;
+    let req = send_credentials("register", username, password).await?;
 
     match req.status() {
         StatusCode::OK => {
@@ -56,11 +60,19 @@ pub async fn register(
                 Ok(text)
             }
         }
-        StatusCode::INTERNAL_SERVER_ERROR => Err("Internal service error!".into()),
-        StatusCode::BAD_REQUEST => Err("Not all inputs provided!".into()),
-        StatusCode::UNAUTHORIZED => Err("No/invalid login!".into()),
-        StatusCode::CONFLICT => Err("User already exists!".into()),
-        e => Err(format!("{e:?}").into()),
+        _ => Err(super::api_error(req).await),
+    }
+}
+
+pub async fn whoami() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("me").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
     }
 }
 
@@ -71,34 +83,30 @@ pub async fn logout() -> Result<(), Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
     let req = client
         .post(path.join("logout").to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
         .send()
         .await?;
 
     match req.status() {
         StatusCode::OK => Ok(()),
-        StatusCode::INTERNAL_SERVER_ERROR => Err("Internal service error!".into()),
-        StatusCode::BAD_REQUEST => Err("Not all inputs provided!".into()),
-        StatusCode::UNAUTHORIZED => Err("Not logged in!".into()),
-        e => Err(format!("{e:?}").into()),
+        _ => Err(super::error_message(req).await.into()),
     }
 }
 
-pub async fn send_message(message: String) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn send_message(room: u64, message: String) -> Result<(), Box<dyn std::error::Error>> {
     let base_url = super::get_base_url().expect("Failed to get base url!");
     let path = Path::new(&base_url);
 
     let client = reqwest::Client::new();
     let req = client
-        .post(path.join("newpost").to_str().unwrap())
+        .post(path.join(format!("rooms/{room}/newpost")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
         .body(message)
         .send()
         .await?;
 
     match req.status() {
         StatusCode::OK => Ok(()),
-        StatusCode::INTERNAL_SERVER_ERROR => Err("Internal service error!".into()),
-        StatusCode::BAD_REQUEST => Err("Not all inputs provided!".into()),
-        StatusCode::UNAUTHORIZED => Err("No/invalid login!".into()),
-        e => Err(format!("{e:?}").into()),
+        _ => Err(super::error_message(req).await.into()),
     }
 }
@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use reqwest::StatusCode;
+
+use super::posts::Cursor;
+
+pub async fn get_conversations() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("dm").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn get_direct_messages(
+    username: &str,
+    cursor: Cursor,
+    limit: u64,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let query = match cursor {
+        Cursor::Latest => format!("?limit={limit}"),
+        Cursor::Before(dm_num) => format!("?before={dm_num}&limit={limit}"),
+        Cursor::After(dm_num) => format!("?after={dm_num}&limit={limit}"),
+    };
+
+    let dm_path = path.join(format!("dm/{username}"));
+    let req = reqwest::get(format!("{}{query}", dm_path.to_str().unwrap())).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn send_direct_message(
+    username: &str,
+    message: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(path.join(format!("dm/{username}")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .body(message)
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(()),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
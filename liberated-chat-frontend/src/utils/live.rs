@@ -0,0 +1,44 @@
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+pub fn get_socket_url() -> Option<String> {
+    let location = web_sys::window()?.location();
+
+    let scheme = if location.protocol().ok()? == "https:" {
+        "wss:"
+    } else {
+        "ws:"
+    };
+
+    Some(format!("{scheme}//{}/ws", location.host().ok()?))
+}
+
+//Opens the live post feed. `on_post` gets the JSON of every new post and `on_close` runs once
+//when the socket goes away for any reason
+pub fn connect(
+    on_open: impl FnMut() + 'static,
+    on_post: impl FnMut(String) + 'static,
+    on_close: impl FnOnce() + 'static,
+) -> Result<WebSocket, JsValue> {
+    let url = get_socket_url().ok_or("Failed to get base url!")?;
+    let socket = WebSocket::new(&url)?;
+
+    let onopen = Closure::<dyn FnMut()>::new(on_open);
+    socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+    onopen.forget();
+
+    let mut on_post = on_post;
+    let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        if let Some(text) = event.data().as_string() {
+            on_post(text);
+        }
+    });
+    socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    let onclose = Closure::once(move |_: CloseEvent| on_close());
+    socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+    onclose.forget();
+
+    Ok(socket)
+}
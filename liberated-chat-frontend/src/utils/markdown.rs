@@ -0,0 +1,393 @@
+//A small, safe subset of Markdown: **bold**, *italics*, `code`, ``` code blocks ```, [links](url),
+//bare links and > quotes, plus @mentions. Everything else, raw HTML included, comes out as escaped
+//text, so the result can go straight into inner_html. Mentions of `me` are marked out from the rest
+pub fn render(markdown: &str, me: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut html = String::new();
+
+    render_blocks(&lines, me, &mut html);
+
+    html
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
+
+fn is_quote(line: &str) -> bool {
+    line.trim_start().starts_with('>')
+}
+
+//Where the code block opened at `start` is closed. A fence that is never closed is just text
+fn find_fence_end(lines: &[&str], start: usize) -> Option<usize> {
+    if !is_fence(lines[start]) {
+        return None;
+    }
+
+    lines[start + 1..]
+        .iter()
+        .position(|line| is_fence(line))
+        .map(|end| start + 1 + end)
+}
+
+fn render_blocks(lines: &[&str], me: &str, html: &mut String) {
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.trim().is_empty() {
+            i += 1;
+        } else if let Some(end) = find_fence_end(lines, i) {
+            html.push_str("<pre><code>");
+            push_escaped(&lines[i + 1..end].join("\n"), html);
+            html.push_str("</code></pre>");
+
+            i = end + 1;
+        } else if is_quote(line) {
+            let quoted: Vec<&str> = lines[i..]
+                .iter()
+                .take_while(|line| is_quote(line))
+                .map(|line| {
+                    //One level at a time, so >> nests a quote inside the quote
+                    let line = &line.trim_start()[1..];
+                    line.strip_prefix(' ').unwrap_or(line)
+                })
+                .collect();
+
+            html.push_str("<blockquote>");
+            render_blocks(&quoted, me, html);
+            html.push_str("</blockquote>");
+
+            i += quoted.len();
+        } else {
+            //Line breaks inside a paragraph are kept, chat messages aren't reflowed prose
+            //The first line always belongs to it, it can be a fence that is never closed
+            let rest = lines[i + 1..]
+                .iter()
+                .take_while(|line| !line.trim().is_empty() && !is_fence(line) && !is_quote(line))
+                .count();
+            let paragraph = &lines[i..=i + rest];
+
+            html.push_str("<p>");
+
+            for (n, line) in paragraph.iter().enumerate() {
+                if n > 0 {
+                    html.push_str("<br>");
+                }
+
+                render_inline(line, me, html);
+            }
+
+            html.push_str("</p>");
+
+            i += paragraph.len();
+        }
+    }
+}
+
+fn render_inline(text: &str, me: &str, html: &mut String) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(|c| c.is_ascii_punctuation()) => {
+                push_escaped_char(chars[i + 1], html);
+                i += 2;
+            }
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+
+                match find_code_end(&chars, i + run, run) {
+                    Some(end) => {
+                        let code: String = chars[i + run..end].iter().collect();
+
+                        html.push_str("<code>");
+                        push_escaped(&code, html);
+                        html.push_str("</code>");
+
+                        i = end + run;
+                    }
+                    None => {
+                        chars[i..i + run]
+                            .iter()
+                            .for_each(|&c| push_escaped_char(c, html));
+                        i += run;
+                    }
+                }
+            }
+            '*' | '_' => {
+                let run = if chars.get(i + 1) == Some(&c) { 2 } else { 1 };
+
+                //_ only counts at the start of a word, so snake_case stays as it is
+                let opens = !(c == '_' && i > 0 && chars[i - 1].is_alphanumeric());
+
+                match find_emphasis_end(&chars, i + run, c, run).filter(|_| opens) {
+                    Some(end) => {
+                        let tag = if run == 2 { "strong" } else { "em" };
+                        let inner: String = chars[i + run..end].iter().collect();
+
+                        html.push_str(&format!("<{tag}>"));
+                        render_inline(&inner, me, html);
+                        html.push_str(&format!("</{tag}>"));
+
+                        i = end + run;
+                    }
+                    None => {
+                        chars[i..i + run]
+                            .iter()
+                            .for_each(|&c| push_escaped_char(c, html));
+                        i += run;
+                    }
+                }
+            }
+            '[' => match parse_link(&chars, i) {
+                Some((label, url, end)) => {
+                    push_link_open(&url, html);
+                    render_inline(&label, me, html);
+                    html.push_str("</a>");
+
+                    i = end;
+                }
+                None => {
+                    push_escaped_char(c, html);
+                    i += 1;
+                }
+            },
+            //Same rules as the server, see mention::parse there
+            '@' if (i == 0 || !is_name_char(chars[i - 1]))
+                && mention_length(&chars[i + 1..]) > 0 =>
+            {
+                let length = mention_length(&chars[i + 1..]);
+                let name: String = chars[i + 1..i + 1 + length].iter().collect();
+
+                let class = if name.to_lowercase() == me.to_lowercase() {
+                    "mention mention-me"
+                } else {
+                    "mention"
+                };
+
+                html.push_str(&format!("<span class=\"{class}\">@"));
+                push_escaped(&name, html);
+                html.push_str("</span>");
+
+                i += 1 + length;
+            }
+            'h' if (i == 0 || chars[i - 1].is_whitespace()) && starts_with_url(&chars[i..]) => {
+                let mut end = i + chars[i..].iter().take_while(|c| !c.is_whitespace()).count();
+
+                //Punctuation right after a link is almost always the end of the sentence
+                while end > i && ".,;:!?)'\"".contains(chars[end - 1]) {
+                    end -= 1;
+                }
+
+                let url: String = chars[i..end].iter().collect();
+
+                push_link_open(&url, html);
+                push_escaped(&url, html);
+                html.push_str("</a>");
+
+                i = end;
+            }
+            c => {
+                push_escaped_char(c, html);
+                i += 1;
+            }
+        }
+    }
+}
+
+//Where a run of exactly `run` backticks closes the code span starting at `start`
+fn find_code_end(chars: &[char], start: usize, run: usize) -> Option<usize> {
+    let mut j = start;
+
+    while j < chars.len() {
+        if chars[j] == '`' {
+            let len = chars[j..].iter().take_while(|&&c| c == '`').count();
+
+            if len == run && j > start {
+                return Some(j);
+            }
+
+            j += len;
+        } else {
+            j += 1;
+        }
+    }
+
+    None
+}
+
+//Where the `run` markers closing the emphasis starting at `start` are. Emphasis can't be empty or
+//start or end with whitespace, and single markers skip over double ones
+fn find_emphasis_end(chars: &[char], start: usize, marker: char, run: usize) -> Option<usize> {
+    chars.get(start).filter(|c| !c.is_whitespace())?;
+
+    let mut j = start + 1;
+
+    while j + run <= chars.len() {
+        let len = chars[j..].iter().take_while(|&&c| c == marker).count();
+
+        if len == 0 {
+            j += 1;
+            continue;
+        }
+
+        let closes = !chars[j - 1].is_whitespace()
+            && (len == run || (run == 2 && len > 2))
+            && !(marker == '_' && chars.get(j + run).is_some_and(|c| c.is_alphanumeric()));
+
+        if closes {
+            return Some(j);
+        }
+
+        j += len;
+    }
+
+    None
+}
+
+//[label](url), with the label, url and where it ends. Only links with a safe scheme count
+fn parse_link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let label_end = start + 1 + chars[start + 1..].iter().position(|&c| c == ']')?;
+
+    if chars.get(label_end + 1) != Some(&'(') {
+        return None;
+    }
+
+    let url_end = label_end + 2 + chars[label_end + 2..].iter().position(|&c| c == ')')?;
+
+    let label: String = chars[start + 1..label_end].iter().collect();
+    let url: String = chars[label_end + 2..url_end].iter().collect();
+    let url = url.trim();
+
+    let safe = ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| url.to_lowercase().starts_with(scheme));
+
+    if label.is_empty() || !safe || url.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((label, url.to_string(), url_end + 1))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-.".contains(c)
+}
+
+//How many characters of the name a mention starts with, leaving off a full stop at the end
+fn mention_length(chars: &[char]) -> usize {
+    let length = chars.iter().take_while(|&&c| is_name_char(c)).count();
+
+    length
+        - chars[..length]
+            .iter()
+            .rev()
+            .take_while(|&&c| c == '.')
+            .count()
+}
+
+fn starts_with_url(chars: &[char]) -> bool {
+    let text: String = chars.iter().take(8).collect();
+
+    text.starts_with("http://") || text.starts_with("https://")
+}
+
+fn push_link_open(url: &str, html: &mut String) {
+    html.push_str("<a href=\"");
+    push_escaped(url, html);
+    html.push_str("\" target=\"_blank\" rel=\"noopener noreferrer nofollow\">");
+}
+
+fn push_escaped(text: &str, html: &mut String) {
+    text.chars().for_each(|c| push_escaped_char(c, html));
+}
+
+fn push_escaped_char(c: char, html: &mut String) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        c => html.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(text: &str) -> Option<(String, String, usize)> {
+        parse_link(&text.chars().collect::<Vec<char>>(), 0)
+    }
+
+    #[test]
+    fn test_raw_html_is_escaped() {
+        assert_eq!(
+            render("<script>alert(1)</script>", "jill"),
+            "<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>"
+        );
+        assert_eq!(
+            render("<img src=x onerror=alert(1)>", "jill"),
+            "<p>&lt;img src=x onerror=alert(1)&gt;</p>"
+        );
+        assert_eq!(
+            render("**<b>hi</b>**", "jill"),
+            "<p><strong>&lt;b&gt;hi&lt;/b&gt;</strong></p>"
+        );
+    }
+
+    #[test]
+    fn test_unsafe_links_are_rejected() {
+        assert!(link("[x](javascript:alert)").is_none());
+        assert!(link("[x](data:text/html,hi)").is_none());
+        assert!(link("[x](JavaScript:alert)").is_none());
+        assert!(link("[x](jAvAsCrIpT:alert)").is_none());
+        assert!(link("[x](vbscript:alert)").is_none());
+
+        //Rejected links are left as text
+        assert_eq!(
+            render("[x](javascript:alert)", "jill"),
+            "<p>[x](javascript:alert)</p>"
+        );
+
+        assert_eq!(
+            link("[x](HTTPS://example.com)"),
+            Some(("x".into(), "HTTPS://example.com".into(), 24))
+        );
+    }
+
+    #[test]
+    fn test_link_quotes_are_escaped() {
+        let html = render("[x](https://example.com/\"onmouseover='alert')", "jill");
+
+        assert!(html.contains(
+            "href=\"https://example.com/&quot;onmouseover=&#39;alert&#39;\" target=\"_blank\""
+        ));
+    }
+
+    #[test]
+    fn test_unclosed_markers_are_text() {
+        assert_eq!(render("**bold", "jill"), "<p>**bold</p>");
+        assert_eq!(render("*italics", "jill"), "<p>*italics</p>");
+        assert_eq!(render("`code", "jill"), "<p>`code</p>");
+        assert_eq!(
+            render("```\nlet x = 1;", "jill"),
+            "<p>```<br>let x = 1;</p>"
+        );
+    }
+
+    #[test]
+    fn test_code_blocks_are_escaped() {
+        assert_eq!(
+            render("```\n<script>\n*not italics*\n```", "jill"),
+            "<pre><code>&lt;script&gt;\n*not italics*</code></pre>"
+        );
+        assert_eq!(render("`<b>`", "jill"), "<p><code>&lt;b&gt;</code></p>");
+    }
+}
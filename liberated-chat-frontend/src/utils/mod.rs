@@ -1,5 +1,95 @@
+use serde::Deserialize;
+use std::{error::Error, fmt};
+use wasm_bindgen::JsCast;
+
 pub mod auth;
+pub mod dm;
+pub mod live;
+pub mod markdown;
+pub mod notifications;
 pub mod posts;
+pub mod rooms;
+pub mod search;
+pub mod sessions;
+pub mod time;
+
+//Same as the server's, see check_csrf there
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+//The server sets this cookie at login, anything that changes state has to send it back in CSRF_HEADER
+pub fn get_csrf_token() -> String {
+    leptos::document()
+        .dyn_into::<web_sys::HtmlDocument>()
+        .ok()
+        .and_then(|document| document.cookie().ok())
+        .and_then(|cookies| {
+            cookies.split("; ").find_map(|cookie| {
+                cookie
+                    .strip_prefix("Liberated-Chat-CSRF=")
+                    .map(String::from)
+            })
+        })
+        .unwrap_or_default()
+}
+
+//Body of every failed request, see AppError on the server
+#[derive(Debug, Deserialize)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+    //Seconds to wait before trying again, from the Retry-After header
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+//Codes never change, so match on those and fall back to the server's own message
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code.as_str() {
+            "internal" => write!(f, "Internal service error!"),
+            "not_logged_in" => write!(f, "No/invalid login!"),
+            "not_in_room" => write!(f, "Join this room first!"),
+            "room_not_found" => write!(f, "No room exists!"),
+            "post_not_found" => write!(f, "No post exists!"),
+            "user_not_found" => write!(f, "No user exists!"),
+            "too_many_attempts" => match self.retry_after {
+                Some(seconds) => write!(f, "Too many attempts, try again in {seconds} seconds!"),
+                None => write!(f, "Too many attempts, try again later!"),
+            },
+            "rate_limited" => match self.retry_after {
+                Some(seconds) => write!(f, "Slow down! You can send again in {seconds} seconds."),
+                None => write!(f, "Slow down! You are sending too fast."),
+            },
+            "duplicate_message" => write!(f, "You just sent that!"),
+            _ => write!(f, "{}!", self.message),
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+//Callers that care which error it was can downcast to ApiError
+pub async fn api_error(req: reqwest::Response) -> Box<dyn Error> {
+    let status = req.status();
+    let retry_after = req
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    match serde_json::from_str::<ApiError>(&req.text().await.unwrap_or_default()) {
+        Ok(error) => ApiError {
+            retry_after,
+            ..error
+        }
+        .into(),
+        Err(_) => format!("{status:?}").into(),
+    }
+}
+
+pub async fn error_message(req: reqwest::Response) -> String {
+    api_error(req).await.to_string()
+}
 
 pub fn get_base_url() -> Option<String> {
     if let Some(window) = leptos::web_sys::window() {
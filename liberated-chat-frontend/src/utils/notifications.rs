@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use reqwest::StatusCode;
+
+pub async fn get_notifications() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("notifications").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+//Marks one notification read, or all of them without a `mention_num`. Returns the notifications
+//as they are afterwards
+pub async fn mark_read(mention_num: Option<u64>) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let read_path = match mention_num {
+        Some(mention_num) => path.join(format!("notifications/{mention_num}/read")),
+        None => path.join("notifications/read"),
+    };
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(read_path.to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
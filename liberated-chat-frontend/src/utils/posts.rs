@@ -1,19 +1,195 @@
 use std::path::Path;
 
-use reqwest::StatusCode;
+use reqwest::{
+    multipart::{Form, Part},
+    StatusCode,
+};
+use wasm_bindgen_futures::JsFuture;
 
-pub async fn get_posts() -> Result<String, Box<dyn std::error::Error>> {
+//Where in the history a page of posts should come from, numbers are post numbers
+#[derive(Clone, Copy)]
+pub enum Cursor {
+    Latest,
+    Before(u64),
+    After(u64),
+}
+
+pub async fn get_posts(
+    room: u64,
+    cursor: Cursor,
+    limit: u64,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let query = match cursor {
+        Cursor::Latest => format!("?limit={limit}"),
+        Cursor::Before(post_num) => format!("?before={post_num}&limit={limit}"),
+        Cursor::After(post_num) => format!("?after={post_num}&limit={limit}"),
+    };
+
+    let posts_path = path.join(format!("rooms/{room}/posts"));
+    let req = reqwest::get(format!("{}{query}", posts_path.to_str().unwrap())).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn edit_post(
+    post_num: u64,
+    message: String,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .patch(path.join(format!("posts/{post_num}")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .body(message)
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn delete_post(post_num: u64) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .delete(path.join(format!("posts/{post_num}")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+//The post and all of its replies, whichever of them `post_num` is
+pub async fn get_thread(post_num: u64) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let thread_path = path.join(format!("posts/{post_num}/thread"));
+    let req = reqwest::get(thread_path.to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+//Returns the reply
+pub async fn reply(post_num: u64, message: String) -> Result<String, Box<dyn std::error::Error>> {
     let base_url = super::get_base_url().expect("Failed to get base url!");
     let path = Path::new(&base_url);
 
-    let req = reqwest::get(path.join("posts").to_str().unwrap()).await?;
+    let reply_path = path.join(format!("posts/{post_num}/reply"));
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(reply_path.to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .body(message)
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn get_limits() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("limits").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+//Posts the files, and the message if there is one, as a single post
+pub async fn upload(
+    room: u64,
+    message: String,
+    files: web_sys::FileList,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let mut form = Form::new().text("message", message);
+
+    for file in (0..files.length()).filter_map(|i| files.get(i)) {
+        let data = JsFuture::from(file.array_buffer())
+            .await
+            .map_err(|e| format!("Failed to read {}: {e:?}", file.name()))?;
+
+        let mut part = Part::bytes(js_sys::Uint8Array::new(&data).to_vec()).file_name(file.name());
+
+        //The server works out the type itself where it can, this is only a hint
+        if !file.type_().is_empty() {
+            part = part.mime_str(&file.type_())?;
+        }
+
+        form = form.part("file", part);
+    }
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(path.join(format!("rooms/{room}/upload")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .multipart(form)
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(()),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+//Adds the reaction, or takes it back if `remove` is set. Returns the updated post
+pub async fn react(
+    post_num: u64,
+    emoji: &str,
+    remove: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let url = path.join(format!(
+        "posts/{post_num}/reactions/{}",
+        String::from(js_sys::encode_uri_component(emoji))
+    ));
+
+    let client = reqwest::Client::new();
+    let req = if remove {
+        client.delete(url.to_str().unwrap())
+    } else {
+        client.put(url.to_str().unwrap())
+    };
+
+    let req = req
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
 
     match req.status() {
         StatusCode::OK => Ok(req.text().await?),
-        StatusCode::INTERNAL_SERVER_ERROR => Err("Internal service error!".into()),
-        StatusCode::BAD_REQUEST => Err("Not all inputs provided!".into()),
-        StatusCode::UNAUTHORIZED => Err("No/invalid login!".into()),
-        StatusCode::CONFLICT => Err("No user exists!".into()),
-        e => Err(format!("{e:?}").into()),
+        _ => Err(super::error_message(req).await.into()),
     }
 }
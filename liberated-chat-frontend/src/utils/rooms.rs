@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use reqwest::StatusCode;
+
+pub async fn get_rooms() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("rooms").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn create_room(name: String) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(path.join("rooms").to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .body(name)
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn join_room(room: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .post(path.join(format!("rooms/{room}/join")).to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(()),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
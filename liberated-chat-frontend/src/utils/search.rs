@@ -0,0 +1,31 @@
+use std::path::Path;
+
+use reqwest::StatusCode;
+
+//Empty filters are left for the server to ignore
+pub async fn search(
+    query: &str,
+    user: &str,
+    from: &str,
+    to: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let encode = |value: &str| String::from(js_sys::encode_uri_component(value));
+    let query = format!(
+        "?q={}&user={}&from={}&to={}",
+        encode(query),
+        encode(user),
+        encode(from),
+        encode(to)
+    );
+
+    let search_path = path.join("search");
+    let req = reqwest::get(format!("{}{query}", search_path.to_str().unwrap())).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
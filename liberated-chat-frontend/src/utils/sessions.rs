@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use reqwest::StatusCode;
+
+pub async fn get_sessions() -> Result<String, Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let req = reqwest::get(path.join("sessions").to_str().unwrap()).await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(req.text().await?),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn revoke_session(session_num: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .delete(
+            path.join(format!("sessions/{session_num}"))
+                .to_str()
+                .unwrap(),
+        )
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(()),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
+
+pub async fn revoke_other_sessions() -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = super::get_base_url().expect("Failed to get base url!");
+    let path = Path::new(&base_url);
+
+    let client = reqwest::Client::new();
+    let req = client
+        .delete(path.join("sessions").to_str().unwrap())
+        .header(super::CSRF_HEADER, super::get_csrf_token())
+        .send()
+        .await?;
+
+    match req.status() {
+        StatusCode::OK => Ok(()),
+        _ => Err(super::error_message(req).await.into()),
+    }
+}
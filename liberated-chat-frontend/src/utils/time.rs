@@ -0,0 +1,38 @@
+use js_sys::Date;
+use wasm_bindgen::JsValue;
+
+//The server sends ISO 8601 times in UTC, the browser knows the viewer's timezone
+pub fn local_time(iso: &str) -> String {
+    let date = Date::new(&JsValue::from_str(iso));
+
+    if date.get_time().is_nan() {
+        return iso.to_string();
+    }
+
+    date.to_locale_string("default", &JsValue::UNDEFINED).into()
+}
+
+//"5 minutes ago" and so on for the last week, the local date and time after that
+pub fn relative_time(iso: &str) -> String {
+    let then = Date::new(&JsValue::from_str(iso)).get_time();
+
+    if then.is_nan() {
+        return iso.to_string();
+    }
+
+    let seconds = ((Date::now() - then) / 1000.0).max(0.0) as u64;
+
+    let (count, unit) = match seconds {
+        0..=59 => return "just now".into(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        86400..=604799 => (seconds / 86400, "day"),
+        _ => return local_time(iso),
+    };
+
+    if count == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{count} {unit}s ago")
+    }
+}
@@ -0,0 +1,53 @@
+-- The schema as it stood when migrations were introduced. IF NOT EXISTS is only here because
+-- unversioned databases already have some of these tables, later migrations don't need it
+CREATE TABLE IF NOT EXISTS users (
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'user',
+    banned INTEGER NOT NULL DEFAULT 0
+) STRICT;
+CREATE INDEX IF NOT EXISTS username_index ON users (username);
+CREATE TABLE IF NOT EXISTS rooms (
+    roomId INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    creator TEXT NOT NULL
+) STRICT;
+INSERT OR IGNORE INTO rooms (roomId, name, creator) VALUES (1, 'general', '');
+CREATE TABLE IF NOT EXISTS room_members (
+    roomId INTEGER NOT NULL REFERENCES rooms (roomId) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    UNIQUE (roomId, username)
+) STRICT;
+CREATE TABLE IF NOT EXISTS posts (
+    postNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    message TEXT NOT NULL,
+    time TEXT NOT NULL,
+    roomId INTEGER NOT NULL DEFAULT 1,
+    editedAt TEXT,
+    deleted INTEGER NOT NULL DEFAULT 0
+) STRICT;
+CREATE TABLE IF NOT EXISTS direct_messages (
+    dmNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    message TEXT NOT NULL,
+    time TEXT NOT NULL
+) STRICT;
+CREATE INDEX IF NOT EXISTS direct_messages_index
+    ON direct_messages (sender, recipient, dmNum);
+CREATE TABLE IF NOT EXISTS moderation_log (
+    actionNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    moderator TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    time TEXT NOT NULL
+) STRICT;
+CREATE TABLE IF NOT EXISTS sessions (
+    username TEXT NOT NULL UNIQUE,
+    sessionId TEXT NOT NULL UNIQUE,
+    expiration INTEGER NOT NULL
+) STRICT;
+CREATE INDEX IF NOT EXISTS sessions_index ON sessions (username, sessionId);
+CREATE INDEX IF NOT EXISTS posts_room_index ON posts (roomId, postNum);
@@ -0,0 +1,18 @@
+-- Lets a user stay logged in on more than one device, and records enough to tell them apart
+CREATE TABLE sessions_new (
+    sessionNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    sessionId TEXT NOT NULL UNIQUE,
+    expiration INTEGER NOT NULL,
+    createdAt INTEGER NOT NULL,
+    lastSeen INTEGER NOT NULL,
+    userAgent TEXT NOT NULL DEFAULT '',
+    ip TEXT NOT NULL DEFAULT ''
+) STRICT;
+-- Sessions always lasted two days, so that is when the existing ones were made
+INSERT INTO sessions_new (username, sessionId, expiration, createdAt, lastSeen)
+    SELECT username, sessionId, expiration, expiration - 172800, expiration - 172800
+    FROM sessions;
+DROP TABLE sessions;
+ALTER TABLE sessions_new RENAME TO sessions;
+CREATE INDEX sessions_username_index ON sessions (username);
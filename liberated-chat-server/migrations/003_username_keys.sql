@@ -0,0 +1,4 @@
+-- Case-insensitive, NFKC-normalized form of each username, so look-alikes can't both register.
+-- SQLite can't compute it, the server fills it in for existing users when it starts
+ALTER TABLE users ADD COLUMN usernameKey TEXT;
+CREATE UNIQUE INDEX users_usernameKey_index ON users (usernameKey);
@@ -0,0 +1,7 @@
+-- Failed logins per username ('user:' prefix) and per IP ('ip:' prefix), for rate limiting
+CREATE TABLE login_failures (
+    key TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    lastFailure INTEGER NOT NULL,
+    lockedUntil INTEGER NOT NULL
+) STRICT;
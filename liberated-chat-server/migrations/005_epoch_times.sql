@@ -0,0 +1,57 @@
+-- Times were text like '01/31/2024 @ 13:45' in UTC, they become Unix timestamps in seconds.
+-- Anything that doesn't parse ends up at 0 rather than failing the migration
+CREATE TABLE posts_new (
+    postNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL,
+    message TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    roomId INTEGER NOT NULL DEFAULT 1,
+    editedAt INTEGER,
+    deleted INTEGER NOT NULL DEFAULT 0
+) STRICT;
+INSERT INTO posts_new (postNum, username, message, time, roomId, editedAt, deleted)
+    SELECT postNum, username, message,
+        coalesce(unixepoch(substr(time, 7, 4) || '-' || substr(time, 1, 2) || '-'
+            || substr(time, 4, 2) || ' ' || substr(time, 14, 5)), 0),
+        roomId,
+        CASE WHEN editedAt IS NOT NULL THEN
+            coalesce(unixepoch(substr(editedAt, 7, 4) || '-' || substr(editedAt, 1, 2) || '-'
+                || substr(editedAt, 4, 2) || ' ' || substr(editedAt, 14, 5)), 0)
+        END,
+        deleted
+    FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_new RENAME TO posts;
+CREATE INDEX posts_room_index ON posts (roomId, postNum);
+
+CREATE TABLE direct_messages_new (
+    dmNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    message TEXT NOT NULL,
+    time INTEGER NOT NULL
+) STRICT;
+INSERT INTO direct_messages_new (dmNum, sender, recipient, message, time)
+    SELECT dmNum, sender, recipient, message,
+        coalesce(unixepoch(substr(time, 7, 4) || '-' || substr(time, 1, 2) || '-'
+            || substr(time, 4, 2) || ' ' || substr(time, 14, 5)), 0)
+    FROM direct_messages;
+DROP TABLE direct_messages;
+ALTER TABLE direct_messages_new RENAME TO direct_messages;
+CREATE INDEX direct_messages_index ON direct_messages (sender, recipient, dmNum);
+
+CREATE TABLE moderation_log_new (
+    actionNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    moderator TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    time INTEGER NOT NULL
+) STRICT;
+INSERT INTO moderation_log_new (actionNum, moderator, action, target, reason, time)
+    SELECT actionNum, moderator, action, target, reason,
+        coalesce(unixepoch(substr(time, 7, 4) || '-' || substr(time, 1, 2) || '-'
+            || substr(time, 4, 2) || ' ' || substr(time, 14, 5)), 0)
+    FROM moderation_log;
+DROP TABLE moderation_log;
+ALTER TABLE moderation_log_new RENAME TO moderation_log;
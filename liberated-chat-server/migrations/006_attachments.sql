@@ -0,0 +1,17 @@
+-- Uploaded files live on disk under ATTACHMENTS_PATH, named by their BLAKE2s hash,
+-- so the same file uploaded twice is only stored once
+CREATE TABLE files (
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL
+) STRICT;
+
+-- A file as attached to a post, under the name and type it was uploaded with
+CREATE TABLE attachments (
+    attachmentNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    postNum INTEGER NOT NULL REFERENCES posts (postNum) ON DELETE CASCADE,
+    hash TEXT NOT NULL REFERENCES files (hash),
+    name TEXT NOT NULL,
+    mime TEXT NOT NULL
+) STRICT;
+CREATE INDEX attachments_post_index ON attachments (postNum);
+CREATE INDEX attachments_hash_index ON attachments (hash);
@@ -0,0 +1,8 @@
+-- One row per emoji a user has reacted to a post with
+CREATE TABLE reactions (
+    postNum INTEGER NOT NULL REFERENCES posts (postNum) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    emoji TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    PRIMARY KEY (postNum, username, emoji)
+) STRICT;
@@ -0,0 +1,4 @@
+-- Replies point at the post that started their thread, threads are only ever one level deep.
+-- Purging a post outright takes its replies with it
+ALTER TABLE posts ADD COLUMN parentPostNum INTEGER REFERENCES posts (postNum) ON DELETE CASCADE;
+CREATE INDEX posts_parent_index ON posts (parentPostNum);
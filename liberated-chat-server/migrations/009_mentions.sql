@@ -0,0 +1,11 @@
+-- One row per user mentioned in a post, doubling as their notification
+CREATE TABLE mentions (
+    mentionNum INTEGER PRIMARY KEY AUTOINCREMENT,
+    postNum INTEGER NOT NULL REFERENCES posts (postNum) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    -- NULL until the user has seen it
+    readAt INTEGER,
+    UNIQUE (postNum, username)
+) STRICT;
+CREATE INDEX mentions_username_index ON mentions (username, readAt);
@@ -0,0 +1,23 @@
+-- Full-text index of post messages. It reads the text from posts rather than keeping a copy, so
+-- the triggers below have to tell it about every change
+CREATE VIRTUAL TABLE posts_search USING fts5 (
+    message,
+    content = 'posts',
+    content_rowid = 'postNum',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+INSERT INTO posts_search (posts_search) VALUES ('rebuild');
+
+CREATE TRIGGER posts_search_insert AFTER INSERT ON posts BEGIN
+    INSERT INTO posts_search (rowid, message) VALUES (new.postNum, new.message);
+END;
+
+CREATE TRIGGER posts_search_delete AFTER DELETE ON posts BEGIN
+    INSERT INTO posts_search (posts_search, rowid, message) VALUES ('delete', old.postNum, old.message);
+END;
+
+-- Covers edits and deletes, which empty the message out
+CREATE TRIGGER posts_search_update AFTER UPDATE OF message ON posts BEGIN
+    INSERT INTO posts_search (posts_search, rowid, message) VALUES ('delete', old.postNum, old.message);
+    INSERT INTO posts_search (rowid, message) VALUES (new.postNum, new.message);
+END;
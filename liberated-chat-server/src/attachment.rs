@@ -0,0 +1,376 @@
+use axum::{
+    async_trait,
+    extract::{multipart::MultipartError, FromRequest, Multipart, Request},
+    http::StatusCode,
+};
+use blake2::{Blake2s256, Digest};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::{error::AppError, types, utils};
+
+//Longest file name kept, in characters
+const MAX_NAME_LENGTH: usize = 255;
+
+const DEFAULT_TYPES: &str =
+    "image/png,image/jpeg,image/gif,image/webp,application/pdf,text/plain,application/zip";
+
+//Limits on what can be attached to posts, and where the files go
+#[derive(Clone, Debug)]
+pub struct AttachmentConfig {
+    pub path: PathBuf,
+    //In bytes, per file
+    pub max_size: usize,
+    pub max_per_post: usize,
+    //MIME types that may be uploaded, as detected by detect_mime
+    pub allowed_types: Vec<String>,
+}
+
+impl AttachmentConfig {
+    //ATTACHMENTS_PATH (defaults to attachments under DATABASE_PATH), MAX_ATTACHMENT_SIZE,
+    //MAX_ATTACHMENTS_PER_POST and ATTACHMENT_TYPES (comma separated)
+    pub fn from_env() -> Self {
+        let path = match env::var("ATTACHMENTS_PATH") {
+            Ok(path) if !path.is_empty() => PathBuf::from(path),
+            _ => Path::new(&env::var("DATABASE_PATH").expect("Set DATABASE_PATH env variable!"))
+                .join("attachments"),
+        };
+
+        let allowed_types = env::var("ATTACHMENT_TYPES")
+            .unwrap_or_else(|_| DEFAULT_TYPES.into())
+            .split(',')
+            .map(|mime| mime.trim().to_lowercase())
+            .filter(|mime| !mime.is_empty())
+            .collect();
+
+        Self {
+            path,
+            max_size: utils::env_or("MAX_ATTACHMENT_SIZE", 8 * 1024 * 1024),
+            max_per_post: utils::env_or("MAX_ATTACHMENTS_PER_POST", 4),
+            allowed_types,
+        }
+    }
+
+    //Largest upload request accepted, every file at full size plus the message
+    pub fn max_upload_size(&self, messages: &super::message::MessageConfig) -> usize {
+        self.max_size * self.max_per_post + messages.max_body_size
+    }
+}
+
+pub struct UploadedFile {
+    pub name: String,
+    pub mime: String,
+    //BLAKE2s of the contents in hex, also the file's name on disk
+    pub hash: String,
+    pub data: Vec<u8>,
+}
+
+//Body of an upload: multipart/form-data with an optional `message` field and one or more `file`
+//fields. Rejects uploads with too many, too large or unsupported files
+pub struct Upload {
+    pub message: String,
+    pub files: Vec<UploadedFile>,
+}
+
+#[async_trait]
+impl FromRequest<types::AppState> for Upload {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &types::AppState) -> Result<Self, Self::Rejection> {
+        let config = &state.attachments.config;
+
+        let mut multipart = Multipart::from_request(req, state)
+            .await
+            .map_err(|_| AppError::BadRequest("Uploads must be multipart/form-data"))?;
+
+        let mut message = String::new();
+        let mut files = Vec::new();
+
+        while let Some(mut field) = multipart.next_field().await.map_err(read_error)? {
+            match field.name() {
+                Some("message") => message = field.text().await.map_err(read_error)?,
+                Some("file") => {
+                    let name = clean_name(field.file_name().unwrap_or_default());
+                    let claimed = field.content_type().unwrap_or_default().to_string();
+
+                    //Stops reading as soon as the file is too large, rather than at the body limit
+                    let mut data = Vec::new();
+
+                    while let Some(chunk) = field.chunk().await.map_err(read_error)? {
+                        if data.len() + chunk.len() > config.max_size {
+                            return Err(AppError::AttachmentTooLarge(config.max_size));
+                        }
+
+                        data.extend_from_slice(&chunk);
+                    }
+
+                    //Browsers send an empty file field when nothing was picked
+                    if data.is_empty() {
+                        continue;
+                    }
+
+                    if files.len() == config.max_per_post {
+                        return Err(AppError::TooManyAttachments(config.max_per_post));
+                    }
+
+                    let mime = detect_mime(&data, &claimed);
+
+                    if !config.allowed_types.contains(&mime) {
+                        return Err(AppError::UnsupportedAttachment(mime));
+                    }
+
+                    files.push(UploadedFile {
+                        name,
+                        mime,
+                        hash: hash(&data),
+                        data,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if files.is_empty() {
+            return Err(AppError::BadRequest("Attach at least one file"));
+        }
+
+        if message.chars().count() > state.messages.max_message_length {
+            return Err(AppError::MessageTooLong(state.messages.max_message_length));
+        }
+
+        Ok(Self { message, files })
+    }
+}
+
+fn read_error(e: MultipartError) -> AppError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge
+    } else {
+        AppError::BadRequest("Failed to read the upload")
+    }
+}
+
+//Keeps the files on disk in step with the files table
+#[derive(Clone)]
+pub struct AttachmentStore {
+    pub config: AttachmentConfig,
+    //Held while files are written or removed, so a purge can't remove a file an upload is reusing
+    lock: Arc<Mutex<()>>,
+}
+
+impl AttachmentStore {
+    pub fn new(config: AttachmentConfig) -> Self {
+        fs::create_dir_all(&config.path).unwrap_or_else(|e| {
+            panic!(
+                "Failed to create attachments folder {}: {e}",
+                config.path.display()
+            )
+        });
+
+        Self {
+            config,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn file_path(&self, hash: &str) -> PathBuf {
+        self.config.path.join(hash)
+    }
+
+    //Stores any files not already on disk, then sends the post with them attached
+    pub fn send(
+        &self,
+        post: &types::InsertPost,
+        files: &[UploadedFile],
+        db: &rusqlite::Connection,
+    ) -> Result<types::Post, AppError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut written = Vec::new();
+
+        let result = files
+            .iter()
+            .try_for_each(|file| {
+                let path = self.file_path(&file.hash);
+
+                if !path.exists() {
+                    write_file(&path, &file.data)?;
+                    written.push(path);
+                }
+
+                Ok::<_, io::Error>(())
+            })
+            .map_err(AppError::from)
+            .and_then(|_| Ok(utils::send_message_with_attachments(post, files, db)?));
+
+        //Nothing refers to the new files if the post didn't make it in
+        if result.is_err() {
+            for path in written {
+                _ = fs::remove_file(path);
+            }
+        }
+
+        result
+    }
+
+    //Removes files no post refers to anymore, returns how many went
+    pub fn purge_unused(&self, db: &rusqlite::Connection) -> Result<usize, AppError> {
+        let _guard = self.lock.lock().unwrap();
+
+        let hashes = utils::purge_unused_files(db)?;
+
+        for hash in &hashes {
+            match fs::remove_file(self.file_path(hash)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    tracing::warn!("Failed to remove attachment {hash}: {e}");
+                }
+                _ => {}
+            }
+        }
+
+        Ok(hashes.len())
+    }
+}
+
+//Written under another name first, so a half written file is never served
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let partial = path.with_extension("part");
+
+    fs::write(&partial, data)?;
+    fs::rename(partial, path)
+}
+
+pub fn hash(data: &[u8]) -> String {
+    Blake2s256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+//Types recognised from their first bytes
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some("image/png"),
+        [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some("application/pdf"),
+        _ => None,
+    }
+}
+
+//What the file is, going by its contents where possible and what the client said otherwise.
+//Images and PDFs are never taken on the client's word, so nothing is shown as an image unless
+//it really is one
+pub fn detect_mime(data: &[u8], claimed: &str) -> String {
+    if let Some(mime) = sniff(data) {
+        return mime.into();
+    }
+
+    let claimed = claimed
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    if claimed.is_empty() || claimed.starts_with("image/") || claimed == "application/pdf" {
+        "application/octet-stream".into()
+    } else {
+        claimed
+    }
+}
+
+//Images are shown in the page, everything else is downloaded
+pub fn content_disposition(attachment: &types::Attachment) -> String {
+    let disposition = if attachment.mime.starts_with("image/") {
+        "inline"
+    } else {
+        "attachment"
+    };
+
+    //RFC 5987, so any name survives the trip whatever characters are in it
+    let name: String = attachment
+        .name
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect();
+
+    format!("{disposition}; filename*=UTF-8''{name}")
+}
+
+//Just the file name, without any folders or control characters the client sent along with it
+fn clean_name(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect();
+
+    match name.trim() {
+        "" => "file".into(),
+        name => name.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_mime() {
+        let png = b"\x89PNG\r\n\x1a\n rest of the image";
+
+        assert_eq!(detect_mime(png, "image/png"), "image/png");
+        //Contents win over what the client says
+        assert_eq!(detect_mime(png, "text/plain"), "image/png");
+        assert_eq!(detect_mime(b"%PDF-1.7", ""), "application/pdf");
+
+        assert_eq!(
+            detect_mime(b"hello", "text/plain; charset=utf-8"),
+            "text/plain"
+        );
+        //Claims to be an image without looking like one
+        assert_eq!(
+            detect_mime(b"<svg onload=alert(1)>", "image/svg+xml"),
+            "application/octet-stream"
+        );
+        assert_eq!(detect_mime(b"hello", ""), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(clean_name("../../etc/passwd"), "passwd");
+        assert_eq!(clean_name("C:\\Users\\john\\cat.png"), "cat.png");
+        assert_eq!(clean_name("a\nb.txt"), "ab.txt");
+        assert_eq!(clean_name(""), "file");
+
+        let attachment = types::Attachment {
+            attachment_num: 1,
+            name: "my \"cat\".png".into(),
+            mime: "image/png".into(),
+            size: 1,
+        };
+
+        assert_eq!(
+            content_disposition(&attachment),
+            "inline; filename*=UTF-8''my%20%22cat%22.png"
+        );
+        assert!(content_disposition(&types::Attachment {
+            mime: "text/plain".into(),
+            ..attachment
+        })
+        .starts_with("attachment;"));
+    }
+}
@@ -0,0 +1,184 @@
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, FromRequestParts, Request},
+    http::{request::Parts, HeaderMap},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use std::net::{IpAddr, SocketAddr};
+
+use super::{error::AppError, types, utils};
+
+//Address the request came from. Behind a reverse proxy every connection comes from the proxy, so
+//with CLIENT_IP_HEADER set the address the proxy passes on in that header is used instead
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<types::AppState> for ClientIp {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &types::AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(addr) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let forwarded = state
+            .client_ip_header
+            .as_deref()
+            .and_then(|name| parts.headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(forwarded_ip);
+
+        Ok(Self(forwarded.unwrap_or(addr.ip())))
+    }
+}
+
+//The last address in the header is the one our own proxy added, anything before it came from the
+//client and could be made up
+fn forwarded_ip(value: &str) -> Option<IpAddr> {
+    value.rsplit(',').next()?.trim().parse().ok()
+}
+
+//Whoever owns the session cookie on the request. Rejects with not_logged_in when there is no
+//valid session and banned when the account has been banned
+pub struct AuthUser {
+    pub username: String,
+    //The cookie value, for handlers that act on the current session only
+    pub session: String,
+    pub role: types::Role,
+}
+
+#[async_trait]
+impl FromRequestParts<types::AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &types::AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+
+        let db = state.pool.get()?;
+
+        let auth_cookie = if let Some(cookie) = jar.get(types::AUTH_COOKIE) {
+            cookie.value().to_string()
+        } else {
+            return Err(AppError::NotLoggedIn);
+        };
+
+        let username = utils::get_username_from_session(&auth_cookie, &db)
+            .map_err(|_| AppError::NotLoggedIn)?;
+
+        let authorized = utils::validate_session(&username, &auth_cookie, &db)
+            .map_err(|_| AppError::NotLoggedIn)?;
+
+        if !authorized {
+            return Err(AppError::NotLoggedIn);
+        }
+
+        let (role, banned) = utils::get_role(&username, &db).map_err(|_| AppError::NotLoggedIn)?;
+
+        if banned {
+            return Err(AppError::Banned);
+        }
+
+        Ok(Self {
+            username,
+            session: auth_cookie,
+            role,
+        })
+    }
+}
+
+//Same as AuthUser, but only lets moderators and admins through
+pub struct Moderator(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<types::AppState> for Moderator {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &types::AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+
+        if user.role >= types::Role::Moderator {
+            Ok(Self(user))
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+}
+
+//Same as AuthUser, but only lets admins through
+pub struct Admin(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<types::AppState> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &types::AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+
+        if user.role == types::Role::Admin {
+            Ok(Self(user))
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+}
+
+//Reads the old Username and Password headers. Raw bytes so non-ASCII passwords still work
+fn credentials_from_headers(headers: &HeaderMap) -> Option<types::Credentials> {
+    let get = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &axum::http::HeaderValue| String::from_utf8(v.as_bytes().to_vec()).ok())
+    };
+
+    Some(types::Credentials {
+        username: get("Username")?,
+        password: get("Password")?,
+    })
+}
+
+//A JSON body of {"username": ..., "password": ...}. The header form is only accepted when
+//ALLOW_HEADER_CREDENTIALS is on, and will be removed
+#[async_trait]
+impl FromRequest<types::AppState> for types::Credentials {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &types::AppState) -> Result<Self, Self::Rejection> {
+        let from_headers = if state.allow_header_credentials {
+            credentials_from_headers(req.headers())
+        } else {
+            None
+        };
+
+        let credentials = match from_headers {
+            Some(credentials) => credentials,
+            None => {
+                let Json(credentials) = Json::<types::Credentials>::from_request(req, state)
+                    .await
+                    .map_err(|_| {
+                        AppError::BadRequest("Expected a JSON body with a username and password")
+                    })?;
+
+                credentials
+            }
+        };
+
+        if credentials.username.is_empty() || credentials.password.is_empty() {
+            return Err(AppError::MissingCredentials);
+        }
+
+        Ok(credentials)
+    }
+}
@@ -0,0 +1,212 @@
+use liberated_chat_server::{
+    policy::{self, Policy},
+    search, types, utils,
+};
+use std::{env, error::Error, process};
+
+const USAGE: &str = "Usage: liberated-chat-admin <command>
+
+Users:
+    users list
+    users create <username> <password>
+    users delete <username>
+    users rename <username> <new username>
+    users password <username> <new password>
+    users role <username> <user|moderator|admin>
+    users unlock <username>
+    users clear
+
+Sessions:
+    sessions list [username]
+    sessions revoke <username>
+    sessions purge
+    sessions clear
+
+Posts:
+    posts delete --id <post number>
+    posts delete --user <username>
+    posts delete --from <YYYY-MM-DD> --to <YYYY-MM-DD>
+    posts clear
+
+Database:
+    stats
+";
+
+enum Command {
+    ListUsers,
+    CreateUser(String, String),
+    DeleteUser(String),
+    RenameUser(String, String),
+    SetPassword(String, String),
+    SetRole(String, types::Role),
+    UnlockUser(String),
+    ClearUsers,
+    ListSessions(Option<String>),
+    RevokeSessions(String),
+    PurgeSessions,
+    ClearSessions,
+    DeletePost(u64),
+    DeletePostsByUser(String),
+    DeletePostsBetween(String, String),
+    ClearPosts,
+    Stats,
+}
+
+fn parse(args: &[&str]) -> Option<Command> {
+    let command = match args {
+        ["users", "list"] => Command::ListUsers,
+        ["users", "create", username, password] => {
+            Command::CreateUser(username.to_string(), password.to_string())
+        }
+        ["users", "delete", username] => Command::DeleteUser(username.to_string()),
+        ["users", "rename", username, new_username] => {
+            Command::RenameUser(username.to_string(), new_username.to_string())
+        }
+        ["users", "password", username, password] => {
+            Command::SetPassword(username.to_string(), password.to_string())
+        }
+        ["users", "role", username, role] => {
+            Command::SetRole(username.to_string(), role.parse().ok()?)
+        }
+        ["users", "unlock", username] => Command::UnlockUser(username.to_string()),
+        ["users", "clear"] => Command::ClearUsers,
+        ["sessions", "list"] => Command::ListSessions(None),
+        ["sessions", "list", username] => Command::ListSessions(Some(username.to_string())),
+        ["sessions", "revoke", username] => Command::RevokeSessions(username.to_string()),
+        ["sessions", "purge"] => Command::PurgeSessions,
+        ["sessions", "clear"] => Command::ClearSessions,
+        ["posts", "delete", "--id", post_num] => Command::DeletePost(post_num.parse().ok()?),
+        ["posts", "delete", "--user", username] => Command::DeletePostsByUser(username.to_string()),
+        ["posts", "delete", "--from", from, "--to", to]
+            if search::is_date(from) && search::is_date(to) =>
+        {
+            Command::DeletePostsBetween(from.to_string(), to.to_string())
+        }
+        ["posts", "clear"] => Command::ClearPosts,
+        ["stats"] => Command::Stats,
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+fn found(found: bool, username: &str) -> Result<(), Box<dyn Error>> {
+    if found {
+        Ok(())
+    } else {
+        Err(format!("No user named {username}").into())
+    }
+}
+
+fn run(command: Command, db: &rusqlite::Connection) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::ListUsers => {
+            for user in utils::list_users(db)? {
+                let banned = if user.banned { " (banned)" } else { "" };
+                println!("{}\t{}{banned}", user.username, user.role);
+            }
+        }
+        Command::CreateUser(username, password) => {
+            //Passwords are up to the admin, but usernames follow the same rules as /register
+            let username = Policy::from_env().check_username(&username)?;
+            let hashed_password = utils::hash(&password).map_err(|e| format!("{e:?}"))?;
+            utils::register_user(&username, &hashed_password, db)?;
+        }
+        Command::DeleteUser(username) => found(utils::delete_user(&username, db)?, &username)?,
+        Command::RenameUser(username, new_username) => {
+            let new_username = Policy::from_env().check_username(&new_username)?;
+            found(utils::rename_user(&username, &new_username, db)?, &username)?
+        }
+        Command::SetPassword(username, password) => {
+            let hashed_password = utils::hash(&password).map_err(|e| format!("{e:?}"))?;
+            found(
+                utils::set_password(&username, &hashed_password, db)?,
+                &username,
+            )?
+        }
+        Command::SetRole(username, role) => {
+            found(utils::user_exists(&username, db)?, &username)?;
+            utils::set_role(&username, role, db)?;
+        }
+        //Same key /login counts failures under
+        Command::UnlockUser(username) => {
+            utils::clear_login_failures(&format!("user:{}", policy::username_key(&username)), db)?
+        }
+        Command::ClearUsers => {
+            db.execute("DELETE FROM users;", [])?;
+        }
+        Command::ListSessions(username) => {
+            for session in utils::list_sessions(username.as_deref(), None, db)? {
+                println!(
+                    "{}\t#{}\t{}\t{}\tlast seen {}\texpires {}",
+                    session.username,
+                    session.session_num,
+                    session.ip,
+                    session.user_agent,
+                    session.last_seen,
+                    session.expiration
+                );
+            }
+        }
+        Command::RevokeSessions(username) => utils::logout(&username, db)?,
+        Command::PurgeSessions => {
+            println!(
+                "Deleted {} expired sessions",
+                utils::purge_expired_sessions(db)?
+            )
+        }
+        Command::ClearSessions => {
+            db.execute("DELETE FROM sessions;", [])?;
+        }
+        Command::DeletePost(post_num) => {
+            println!("Deleted {} posts", utils::purge_post(post_num, db)?)
+        }
+        Command::DeletePostsByUser(username) => {
+            println!(
+                "Deleted {} posts",
+                utils::purge_posts_by_user(&username, db)?
+            )
+        }
+        Command::DeletePostsBetween(from, to) => {
+            println!(
+                "Deleted {} posts",
+                utils::purge_posts_between(&from, &to, db)?
+            )
+        }
+        Command::ClearPosts => {
+            db.execute("DELETE FROM posts;", [])?;
+        }
+        Command::Stats => {
+            let stats = utils::get_stats(db)?;
+
+            println!("Users: {} ({} banned)", stats.users, stats.banned_users);
+            println!("Sessions: {}", stats.sessions);
+            println!("Rooms: {}", stats.rooms);
+            println!("Posts: {} ({} deleted)", stats.posts, stats.deleted_posts);
+            println!("Direct messages: {}", stats.direct_messages);
+            println!("Database size: {} KiB", stats.size_bytes / 1024);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    dotenv::dotenv().expect("Failed to load .env file. Is there one?");
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let Some(command) = parse(&args) else {
+        eprint!("{USAGE}");
+        process::exit(2);
+    };
+
+    let pool = types::AppState::open_pool();
+    let db = pool.get().expect("Failed to access database!");
+
+    if let Err(e) = run(command, &db) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,273 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use super::{rate_limit, types};
+
+//Everything a handler can fail with. Clients get {"code": ..., "message": ...} JSON back,
+//where `code` never changes once released and `message` is only meant for people
+#[derive(Debug)]
+pub enum AppError {
+    //Holds the underlying error for the server log, clients only ever see a generic message
+    Internal(String),
+    //Requests that can't be understood, with what was wrong with them
+    BadRequest(&'static str),
+    //Rejected by the username or password policy, with the reason why
+    InvalidUsername(String),
+    InvalidPassword(String),
+    //Over the body size limit
+    PayloadTooLarge,
+    //Messages must have something in them, and at most the given number of characters
+    EmptyMessage,
+    MessageTooLong(usize),
+    //Attachment limits, holding the limit that was hit or the type that isn't allowed
+    AttachmentTooLarge(usize),
+    TooManyAttachments(usize),
+    UnsupportedAttachment(String),
+    MissingCredentials,
+    InvalidRoomName,
+    InvalidRole,
+    InvalidRecipient,
+    CannotChangeOwnRole,
+    InvalidEmoji,
+    //Holds how many different emoji a user may react to one post with
+    TooManyReactions(usize),
+    NotLoggedIn,
+    InvalidCredentials,
+    Banned,
+    CsrfFailed,
+    NotInRoom,
+    //Logged in, but not allowed to do this
+    Forbidden,
+    NotFound,
+    PostNotFound,
+    RoomNotFound,
+    UserNotFound,
+    SessionNotFound,
+    AttachmentNotFound,
+    UsernameTaken,
+    RoomTaken,
+    //Too many failed logins, holds how many seconds until the next try is allowed
+    TooManyAttempts(u64),
+    //Posting too fast, holds how many seconds until the next post is allowed
+    RateLimited(u64),
+    DuplicateMessage,
+}
+
+impl AppError {
+    //For inserts of something that has to be unique. Only a clash becomes `taken`, anything else
+    //is still broken server side
+    pub fn or_taken(e: rusqlite::Error, taken: AppError) -> AppError {
+        match e {
+            rusqlite::Error::SqliteFailure(failure, _)
+                if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                taken
+            }
+            e => e.into(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_)
+            | AppError::InvalidUsername(_)
+            | AppError::InvalidPassword(_)
+            | AppError::MissingCredentials
+            | AppError::InvalidRoomName
+            | AppError::InvalidRole
+            | AppError::InvalidRecipient
+            | AppError::CannotChangeOwnRole
+            | AppError::InvalidEmoji
+            | AppError::TooManyReactions(_)
+            | AppError::TooManyAttachments(_) => StatusCode::BAD_REQUEST,
+            AppError::NotLoggedIn | AppError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            AppError::Banned | AppError::CsrfFailed | AppError::NotInRoom | AppError::Forbidden => {
+                StatusCode::FORBIDDEN
+            }
+            AppError::NotFound
+            | AppError::PostNotFound
+            | AppError::RoomNotFound
+            | AppError::UserNotFound
+            | AppError::SessionNotFound
+            | AppError::AttachmentNotFound => StatusCode::NOT_FOUND,
+            AppError::UsernameTaken | AppError::RoomTaken => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge | AppError::AttachmentTooLarge(_) => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            AppError::UnsupportedAttachment(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::EmptyMessage | AppError::MessageTooLong(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::TooManyAttempts(_)
+            | AppError::RateLimited(_)
+            | AppError::DuplicateMessage => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Internal(_) => "internal",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InvalidUsername(_) => "invalid_username",
+            AppError::InvalidPassword(_) => "invalid_password",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::EmptyMessage => "empty_message",
+            AppError::MessageTooLong(_) => "message_too_long",
+            AppError::AttachmentTooLarge(_) => "attachment_too_large",
+            AppError::TooManyAttachments(_) => "too_many_attachments",
+            AppError::UnsupportedAttachment(_) => "unsupported_attachment",
+            AppError::MissingCredentials => "missing_credentials",
+            AppError::InvalidRoomName => "invalid_room_name",
+            AppError::InvalidRole => "invalid_role",
+            AppError::InvalidRecipient => "invalid_recipient",
+            AppError::CannotChangeOwnRole => "cannot_change_own_role",
+            AppError::InvalidEmoji => "invalid_emoji",
+            AppError::TooManyReactions(_) => "too_many_reactions",
+            AppError::NotLoggedIn => "not_logged_in",
+            AppError::InvalidCredentials => "invalid_credentials",
+            AppError::Banned => "banned",
+            AppError::CsrfFailed => "csrf_failed",
+            AppError::NotInRoom => "not_in_room",
+            AppError::Forbidden => "forbidden",
+            AppError::NotFound => "not_found",
+            AppError::PostNotFound => "post_not_found",
+            AppError::RoomNotFound => "room_not_found",
+            AppError::UserNotFound => "user_not_found",
+            AppError::SessionNotFound => "session_not_found",
+            AppError::AttachmentNotFound => "attachment_not_found",
+            AppError::UsernameTaken => "username_taken",
+            AppError::RoomTaken => "room_taken",
+            AppError::TooManyAttempts(_) => "too_many_attempts",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::DuplicateMessage => "duplicate_message",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Internal(_) => "Internal service error".into(),
+            AppError::BadRequest(message) => message.to_string(),
+            AppError::InvalidUsername(reason) | AppError::InvalidPassword(reason) => reason.clone(),
+            AppError::PayloadTooLarge => "Request body is too large".into(),
+            AppError::EmptyMessage => "Messages can't be empty".into(),
+            AppError::MessageTooLong(max) => {
+                format!("Messages can't be longer than {max} characters")
+            }
+            AppError::AttachmentTooLarge(max) => {
+                format!("Attachments can't be larger than {} KiB", max / 1024)
+            }
+            AppError::TooManyAttachments(max) => {
+                format!("At most {max} files can be attached to a post")
+            }
+            AppError::UnsupportedAttachment(mime) => {
+                format!("Files of type {mime} can't be attached")
+            }
+            AppError::MissingCredentials => "Both a username and password are required".into(),
+            AppError::InvalidRoomName => format!(
+                "Room names must be between 1 and {} characters",
+                types::MAX_ROOM_NAME_LENGTH
+            ),
+            AppError::InvalidRole => "Roles are user, moderator or admin".into(),
+            AppError::InvalidRecipient => "You can't message yourself".into(),
+            AppError::CannotChangeOwnRole => "You can't change your own role".into(),
+            AppError::InvalidEmoji => "Reactions can only be emoji".into(),
+            AppError::TooManyReactions(max) => {
+                format!("You can react to a post with at most {max} different emoji")
+            }
+            AppError::NotLoggedIn => "Not logged in".into(),
+            AppError::InvalidCredentials => "Wrong username or password".into(),
+            AppError::Banned => "This account has been banned".into(),
+            AppError::CsrfFailed => "Missing or wrong CSRF token".into(),
+            AppError::NotInRoom => "Join the room first".into(),
+            AppError::Forbidden => "You aren't allowed to do that".into(),
+            AppError::NotFound => "Nothing to see here. Error 404 page not found.".into(),
+            AppError::PostNotFound => "No such post".into(),
+            AppError::RoomNotFound => "No such room".into(),
+            AppError::UserNotFound => "No such user".into(),
+            AppError::SessionNotFound => "No such session".into(),
+            AppError::AttachmentNotFound => "No such attachment".into(),
+            AppError::UsernameTaken => "User already exists".into(),
+            AppError::RoomTaken => "Room already exists".into(),
+            AppError::TooManyAttempts(retry_after) => {
+                format!("Too many failed logins, try again in {retry_after} seconds")
+            }
+            AppError::RateLimited(retry_after) => {
+                format!("Slow down, you can post again in {retry_after} seconds")
+            }
+            AppError::DuplicateMessage => "You just sent that".into(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if let AppError::Internal(e) = &self {
+            tracing::error!("Internal error: {e}");
+        }
+
+        let body = types::ErrorResponse {
+            code: self.code().into(),
+            message: self.message(),
+        };
+
+        let mut response = (self.status(), Json(body)).into_response();
+
+        if let AppError::TooManyAttempts(retry_after) | AppError::RateLimited(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+
+        response
+    }
+}
+
+//Lets handlers use ? on anything that can only fail because something is broken server side
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(e: r2d2::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<rate_limit::Limited> for AppError {
+    fn from(limited: rate_limit::Limited) -> Self {
+        match limited {
+            rate_limit::Limited::TooFast(retry_after) => AppError::RateLimited(retry_after),
+            rate_limit::Limited::Duplicate => AppError::DuplicateMessage,
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for AppError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
@@ -0,0 +1,13 @@
+//Shared by the server and the liberated-chat-admin binary
+pub mod attachment;
+pub mod auth;
+pub mod error;
+pub mod mention;
+pub mod message;
+mod migrations;
+pub mod policy;
+pub mod rate_limit;
+pub mod reaction;
+pub mod search;
+pub mod types;
+pub mod utils;
@@ -1,16 +1,25 @@
-mod types;
-mod utils;
-
 use axum::{
     body::Bytes,
-    extract::{Request, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use axum_extra::extract::{cookie::Cookie, CookieJar};
-use std::env;
+use futures_util::{stream, SinkExt, Stream, StreamExt};
+use liberated_chat_server::{
+    attachment, auth, error::AppError, mention, message, policy, reaction, search, types, utils,
+};
+use std::{env, fs, future, net::SocketAddr, time::Duration};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 use tower_http::{compression::CompressionLayer, services::ServeDir};
 
 const FAVICON: &[u8] = include_bytes!("../favicon.ico");
@@ -19,186 +28,1146 @@ async fn favicon() -> Bytes {
     Bytes::from(FAVICON)
 }
 
+//Max-Age keeps the browser's copy in step with the server's expiry
+fn session_cookie(session: String, lifetime: u64, config: &types::CookieConfig) -> Cookie<'static> {
+    Cookie::build((types::AUTH_COOKIE, session))
+        .path("/")
+        .http_only(true)
+        .secure(config.secure)
+        .same_site(config.same_site)
+        .max_age(time::Duration::seconds(lifetime as i64))
+        .build()
+}
+
+//Lives exactly as long as the session cookie, but the frontend has to be able to read it
+fn csrf_cookie(token: String, lifetime: u64, config: &types::CookieConfig) -> Cookie<'static> {
+    Cookie::build((types::CSRF_COOKIE, token))
+        .path("/")
+        .secure(config.secure)
+        .same_site(config.same_site)
+        .max_age(time::Duration::seconds(lifetime as i64))
+        .build()
+}
+
 async fn login(
     jar: CookieJar,
     State(state): State<types::AppState>,
-    req: Request,
-) -> Result<CookieJar, StatusCode> {
-    let headers = req.headers();
-    let username = headers
-        .get("Username")
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .to_str()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-    let password = headers
-        .get("Password")
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .to_str()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    if username.is_empty() || password.is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    let db = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(cookie) = jar.get("Liberated-Chat-Auth") {
-        if let Ok(v) = utils::validate_session(username, cookie.value(), &db) {
-            if v {
-                return Err(StatusCode::OK);
-            }
+    auth::ClientIp(ip): auth::ClientIp,
+    headers: HeaderMap,
+    credentials: types::Credentials,
+) -> Result<CookieJar, AppError> {
+    //Stored usernames are normalized, so "ｊｏｈｎ" logs in as "john"
+    let username = policy::normalize(&credentials.username);
+    let username = username.as_str();
+
+    let db = state.pool.get()?;
+
+    //Already logged in as this user, nothing to do
+    if let Some(cookie) = jar.get(types::AUTH_COOKIE) {
+        if let Ok(true) = utils::validate_session(username, cookie.value(), &db) {
+            return Ok(jar);
         }
     }
 
-    let valid =
-        utils::validate_password(username, password, &db).map_err(|_| StatusCode::CONFLICT)?;
+    //Checked before hashing anything, so locked out guesses cost next to nothing
+    let user_key = format!("user:{}", policy::username_key(username));
+    let ip_key = format!("ip:{ip}");
 
-    if valid {
-        let session = utils::generate_session(username, &db)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    check_login_lockout(&[&user_key, &ip_key], &db)?;
 
-        Ok(jar.add(Cookie::new("Liberated-Chat-Auth", session)))
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+    //Same answer whether or not the user exists, so usernames can't be probed for
+    if !utils::validate_password(username, &credentials.password, &db)? {
+        let limits = &state.login_limits;
+
+        utils::record_login_failure(&user_key, limits.max_failures, limits, &db)?;
+        utils::record_login_failure(&ip_key, limits.max_failures_per_ip, limits, &db)?;
+
+        return Err(AppError::InvalidCredentials);
+    }
+
+    //The IP's failures stand, or one account of your own would reset them
+    utils::clear_login_failures(&user_key, &db)?;
+
+    let (_, banned) = utils::get_role(username, &db)?;
+
+    if banned {
+        return Err(AppError::Banned);
+    }
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    let session =
+        utils::generate_session(username, user_agent, &ip.to_string(), &state.sessions, &db)?;
+
+    let lifetime = state.sessions.idle_timeout.min(state.sessions.max_age);
+
+    let csrf_token = uuid::Uuid::new_v4().to_string();
+
+    Ok(jar
+        .add(session_cookie(session, lifetime, &state.cookies))
+        .add(csrf_cookie(csrf_token, lifetime, &state.cookies)))
+}
+
+//Fails with however long the longest lockout among keys has left
+fn check_login_lockout(keys: &[&str], db: &rusqlite::Connection) -> Result<(), AppError> {
+    let mut retry_after = None;
+
+    for key in keys {
+        retry_after = retry_after.max(utils::get_login_lockout(key, db)?);
+    }
+
+    match retry_after {
+        Some(retry_after) => Err(AppError::TooManyAttempts(retry_after)),
+        None => Ok(()),
     }
 }
 
 async fn register(
     State(state): State<types::AppState>,
-    req: Request,
-) -> Result<String, StatusCode> {
-    let headers = req.headers();
-    let username = headers
-        .get("Username")
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .to_str()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-    let password = headers
-        .get("Password")
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .to_str()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    if username.is_empty() || password.is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    let hashed_password = utils::hash(password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let db = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    utils::register_user(username, &hashed_password, &db).map_err(|_| StatusCode::CONFLICT)?;
+    credentials: types::Credentials,
+) -> Result<String, AppError> {
+    let username = state
+        .policy
+        .check_username(&credentials.username)
+        .map_err(AppError::InvalidUsername)?;
+
+    state
+        .policy
+        .check_password(&credentials.password, &username)
+        .map_err(AppError::InvalidPassword)?;
+
+    let hashed_password = utils::hash(&credentials.password)?;
+
+    let db = state.pool.get()?;
+
+    utils::register_user(&username, &hashed_password, &db)
+        .map_err(|e| AppError::or_taken(e, AppError::UsernameTaken))?;
+
     Ok("Success!".into())
 }
 
-async fn posts(jar: CookieJar, State(state): State<types::AppState>) -> Result<String, StatusCode> {
-    let db = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn posts(
+    user: auth::AuthUser,
+    state: State<types::AppState>,
+    query: Query<types::PostsQuery>,
+) -> Result<String, AppError> {
+    room_posts(user, state, Path(types::GENERAL_ROOM), query).await
+}
 
-    let auth_cookie = if let Some(cookie) = jar.get("Liberated-Chat-Auth") {
-        cookie.value().to_string()
-    } else {
-        return Err(StatusCode::UNAUTHORIZED);
+async fn room_posts(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(room): Path<u64>,
+    Query(query): Query<types::PostsQuery>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    if !utils::room_exists(room, &db)? {
+        return Err(AppError::RoomNotFound);
+    }
+
+    if !utils::can_access_room(&user.username, room, &db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(types::DEFAULT_PAGE_SIZE)
+        .clamp(1, types::MAX_PAGE_SIZE);
+
+    let posts = match (query.before, query.after) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest("Use either before or after, not both"))
+        }
+        (None, Some(after)) => utils::get_posts_after(room, after, limit, &db),
+        (before, None) => utils::get_posts(room, before, limit, &db),
+    }?;
+
+    Ok(serde_json::to_string(&posts)?)
+}
+
+async fn posts_stream(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    req: Request,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let db = state.pool.get()?;
+
+    //Browsers send this back on reconnect with the id of the last event they saw
+    let last_seen: Option<u64> = req
+        .headers()
+        .get("Last-Event-ID")
+        .map(|id| {
+            id.to_str()
+                .ok()
+                .and_then(|id| id.parse().ok())
+                .ok_or(AppError::BadRequest("Last-Event-ID must be a post number"))
+        })
+        .transpose()?;
+
+    //Only events for posts newer than any sent before carry an id, so edits, deletes and
+    //reactions to older posts don't move the client's Last-Event-ID backwards. Looked up before
+    //subscribing, so posts made in the meantime still get theirs
+    let mut last_id = match last_seen {
+        Some(last_seen) => last_seen,
+        None => utils::get_last_post_num(&db)?,
     };
 
-    let username = utils::get_username_from_session(&auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    //Subscribe before looking up missed posts so nothing slips through the gap
+    let posts_rx = state.posts_tx.subscribe();
+
+    let (missed, reload) = match last_seen {
+        Some(last_seen) => {
+            let missed = utils::get_visible_posts_after(
+                &user.username,
+                last_seen,
+                types::MAX_PAGE_SIZE + 1,
+                &db,
+            )?;
 
-    let authorized = utils::validate_session(&username, &auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+            //Too far behind to replay, the client fetches a fresh page instead
+            if missed.len() as u64 > types::MAX_PAGE_SIZE {
+                (Vec::new(), true)
+            } else {
+                (missed, false)
+            }
+        }
+        None => (Vec::new(), false),
+    };
 
-    if authorized {
-        let posts = utils::get_posts(&db).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let already_sent = missed.clone();
 
-        serde_json::to_string(&posts).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
-    }
+    let live = stream::unfold(posts_rx, |mut posts_rx| async move {
+        loop {
+            match posts_rx.recv().await {
+                Ok(post) => return Some((post, posts_rx)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |post| {
+        //Posts made between subscribing and looking up the missed ones come through twice.
+        //Anything else is new, or an edit, delete or reaction reusing an older post's number
+        let unseen = !already_sent.contains(post);
+
+        let visible = unseen
+            && state
+                .pool
+                .get()
+                .ok()
+                .and_then(|db| utils::can_access_room(&user.username, post.room, &db).ok())
+                .unwrap_or(false);
+
+        future::ready(visible)
+    });
+
+    let posts = stream::iter(missed).chain(live).map(move |post| {
+        let event = Event::default().event("post");
+
+        //EventSource keeps the last id it saw when an event has none
+        let event = if post.post_num > last_id {
+            last_id = post.post_num;
+            event.id(post.post_num.to_string())
+        } else {
+            event
+        };
+
+        event.json_data(&post)
+    });
+
+    let events =
+        stream::iter(reload.then(|| Ok(Event::default().event("reload").data("")))).chain(posts);
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
 }
 
 async fn newpost(
-    jar: CookieJar,
+    user: auth::AuthUser,
+    state: State<types::AppState>,
+    message: message::Message,
+) -> Result<String, AppError> {
+    room_newpost(user, state, Path(types::GENERAL_ROOM), message).await
+}
+
+async fn room_newpost(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(room): Path<u64>,
+    message::Message(message): message::Message,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    if !utils::room_exists(room, &db)? {
+        return Err(AppError::RoomNotFound);
+    }
+
+    if !utils::can_access_room(&user.username, room, &db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    state.post_limiter.check(&user.username, &message)?;
+
+    let post = types::InsertPost {
+        room,
+        user: user.username,
+        message,
+        time: utils::get_time(),
+        parent_post_num: None,
+    };
+
+    let post = utils::send_message(&post, &db)?;
+
+    mention::record(&post, &db)?;
+
+    //Only fails when nobody is listening, which is fine
+    _ = state.posts_tx.send(post);
+
+    Ok("Success".into())
+}
+
+async fn room_upload(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(room): Path<u64>,
+    upload: attachment::Upload,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    if !utils::room_exists(room, &db)? {
+        return Err(AppError::RoomNotFound);
+    }
+
+    if !utils::can_access_room(&user.username, room, &db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    //The hashes stand in for the files, so uploading the same thing twice counts as a duplicate
+    let fingerprint = upload
+        .files
+        .iter()
+        .fold(upload.message.clone(), |fingerprint, file| {
+            fingerprint + &file.hash
+        });
+
+    state.post_limiter.check(&user.username, &fingerprint)?;
+
+    let post = types::InsertPost {
+        room,
+        user: user.username,
+        message: upload.message,
+        time: utils::get_time(),
+        parent_post_num: None,
+    };
+
+    let post = state.attachments.send(&post, &upload.files, &db)?;
+
+    mention::record(&post, &db)?;
+
+    _ = state.posts_tx.send(post);
+
+    Ok("Success".into())
+}
+
+//Only for people who can see the post the attachment is on
+async fn attachment(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(attachment_num): Path<u64>,
+) -> Result<Response, AppError> {
+    let db = state.pool.get()?;
+
+    let (attachment, room, hash) =
+        utils::get_attachment(attachment_num, &db)?.ok_or(AppError::AttachmentNotFound)?;
+
+    if !utils::can_access_room(&user.username, room, &db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    let data = fs::read(state.attachments.file_path(&hash))?;
+
+    //Served from the same origin as the app, so uploads must never be able to run as part of it
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.mime.clone()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment::content_disposition(&attachment),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".into()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".into()),
+            //An attachment never changes once uploaded
+            (
+                header::CACHE_CONTROL,
+                "private, max-age=31536000, immutable".into(),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+async fn editpost(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(post_num): Path<u64>,
+    message::Message(message): message::Message,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let post = utils::get_post(post_num, &db).map_err(|_| AppError::PostNotFound)?;
+
+    if post.deleted {
+        return Err(AppError::PostNotFound);
+    }
+
+    if post.user != user.username {
+        return Err(AppError::Forbidden);
+    }
+
+    let post = utils::edit_post(post_num, &message, &db)?;
+
+    //Only people newly mentioned hear about it
+    mention::record(&post, &db)?;
+
+    //Live clients swap in the new version of the post
+    _ = state.posts_tx.send(post.clone());
+
+    Ok(serde_json::to_string(&post)?)
+}
+
+async fn deletepost(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(post_num): Path<u64>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let post = utils::get_post(post_num, &db).map_err(|_| AppError::PostNotFound)?;
+
+    if post.deleted {
+        return Err(AppError::PostNotFound);
+    }
+
+    if post.user != user.username {
+        return Err(AppError::Forbidden);
+    }
+
+    let post = utils::delete_post(post_num, &db)?;
+
+    _ = state.posts_tx.send(post.clone());
+    send_parent(&post, &state, &db)?;
+
+    Ok(serde_json::to_string(&post)?)
+}
+
+//Reacting twice with the same emoji is a no-op, as is taking back a reaction that isn't there
+async fn react(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path((post_num, emoji)): Path<(u64, String)>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let post = visible_post(&user.username, post_num, &db)?;
+
+    if !reaction::is_emoji(&emoji) {
+        return Err(AppError::InvalidEmoji);
+    }
+
+    let reacted = post
+        .reactions
+        .iter()
+        .any(|reaction| reaction.emoji == emoji && reaction.users.contains(&user.username));
+
+    if !reacted
+        && utils::count_reactions(post_num, &user.username, &db)?
+            >= reaction::MAX_REACTIONS_PER_USER
+    {
+        return Err(AppError::TooManyReactions(reaction::MAX_REACTIONS_PER_USER));
+    }
+
+    utils::add_reaction(post_num, &user.username, &emoji, &db)?;
+
+    let post = utils::get_post(post_num, &db)?;
+
+    _ = state.posts_tx.send(post.clone());
+
+    Ok(serde_json::to_string(&post)?)
+}
+
+async fn unreact(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path((post_num, emoji)): Path<(u64, String)>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    visible_post(&user.username, post_num, &db)?;
+
+    utils::remove_reaction(post_num, &user.username, &emoji, &db)?;
+
+    let post = utils::get_post(post_num, &db)?;
+
+    _ = state.posts_tx.send(post.clone());
+
+    Ok(serde_json::to_string(&post)?)
+}
+
+//Anyone who can see a post can react or reply to it, unless it has been deleted
+fn visible_post(
+    username: &str,
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<types::Post, AppError> {
+    let post = utils::get_post(post_num, db).map_err(|_| AppError::PostNotFound)?;
+
+    if post.deleted {
+        return Err(AppError::PostNotFound);
+    }
+
+    if !utils::can_access_room(username, post.room, db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    Ok(post)
+}
+
+//Replies always go on the post that started the thread, so threads stay one level deep
+async fn reply(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(post_num): Path<u64>,
+    message::Message(message): message::Message,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let parent = visible_post(&user.username, post_num, &db)?;
+
+    state.post_limiter.check(&user.username, &message)?;
+
+    let post = types::InsertPost {
+        room: parent.room,
+        user: user.username,
+        message,
+        time: utils::get_time(),
+        parent_post_num: Some(parent.parent_post_num.unwrap_or(parent.post_num)),
+    };
+
+    let post = utils::send_message(&post, &db)?;
+
+    mention::record(&post, &db)?;
+
+    _ = state.posts_tx.send(post.clone());
+    send_parent(&post, &state, &db)?;
+
+    Ok(serde_json::to_string(&post)?)
+}
+
+//The post a thread started from and every reply to it, whichever of them is asked for
+async fn thread(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(post_num): Path<u64>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let thread = utils::get_thread(post_num, &db).map_err(|_| AppError::PostNotFound)?;
+
+    if !utils::can_access_room(&user.username, thread.post.room, &db)? {
+        return Err(AppError::NotInRoom);
+    }
+
+    Ok(serde_json::to_string(&thread)?)
+}
+
+//Live clients need the post a reply is on again whenever its reply count changes
+fn send_parent(
+    post: &types::Post,
+    state: &types::AppState,
+    db: &rusqlite::Connection,
+) -> Result<(), AppError> {
+    if let Some(parent_post_num) = post.parent_post_num {
+        _ = state.posts_tx.send(utils::get_post(parent_post_num, db)?);
+    }
+
+    Ok(())
+}
+
+async fn notifications(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let notifications = utils::get_notifications(&user.username, mention::MAX_NOTIFICATIONS, &db)?;
+
+    Ok(serde_json::to_string(&notifications)?)
+}
+
+async fn read_notifications(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    mark_read(&user.username, None, &state)
+}
+
+async fn read_notification(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(mention_num): Path<u64>,
+) -> Result<String, AppError> {
+    mark_read(&user.username, Some(mention_num), &state)
+}
+
+//Both mark-as-read routes answer with the notifications as they are afterwards
+fn mark_read(
+    username: &str,
+    mention_num: Option<u64>,
+    state: &types::AppState,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    utils::mark_notifications_read(username, mention_num, &db)?;
+
+    let notifications = utils::get_notifications(username, mention::MAX_NOTIFICATIONS, &db)?;
+
+    Ok(serde_json::to_string(&notifications)?)
+}
+
+async fn search(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Query(query): Query<types::SearchQuery>,
+) -> Result<String, AppError> {
+    if query.q.chars().count() > search::MAX_QUERY_LENGTH {
+        return Err(AppError::BadRequest("Search is too long"));
+    }
+
+    let terms = search::to_match_query(&query.q)
+        .ok_or(AppError::BadRequest("Search for at least one word"))?;
+
+    let (author, from, to) = (
+        non_empty(&query.user),
+        non_empty(&query.from),
+        non_empty(&query.to),
+    );
+
+    if ![from, to].into_iter().flatten().all(search::is_date) {
+        return Err(AppError::BadRequest("Dates must be YYYY-MM-DD"));
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(search::DEFAULT_RESULTS)
+        .clamp(1, search::MAX_RESULTS);
+
+    let db = state.pool.get()?;
+
+    let results = utils::search_posts(&user.username, &terms, author, from, to, limit, &db)?;
+
+    Ok(serde_json::to_string(&results)?)
+}
+
+//Forms send empty fields for filters that weren't filled in
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.is_empty())
+}
+
+async fn me(user: auth::AuthUser) -> String {
+    user.username
+}
+
+//So clients can check messages before sending them
+async fn limits(State(state): State<types::AppState>) -> Result<String, AppError> {
+    Ok(serde_json::to_string(&state.messages)?)
+}
+
+async fn rooms(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let rooms = utils::get_rooms(&user.username, &db)?;
+
+    Ok(serde_json::to_string(&rooms)?)
+}
+
+async fn newroom(
+    user: auth::AuthUser,
     State(state): State<types::AppState>,
     body: Bytes,
-) -> Result<String, StatusCode> {
-    let db = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let auth_cookie = if let Some(cookie) = jar.get("Liberated-Chat-Auth") {
-        cookie.value().to_string()
-    } else {
-        return Err(StatusCode::UNAUTHORIZED);
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let name = std::str::from_utf8(body.as_ref())
+        .map_err(|_| AppError::InvalidRoomName)?
+        .trim();
+
+    if name.is_empty() || name.chars().count() > types::MAX_ROOM_NAME_LENGTH {
+        return Err(AppError::InvalidRoomName);
+    }
+
+    let room = utils::create_room(name, &user.username, &db)
+        .map_err(|e| AppError::or_taken(e, AppError::RoomTaken))?;
+
+    Ok(serde_json::to_string(&room)?)
+}
+
+async fn join(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(room): Path<u64>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    if !utils::room_exists(room, &db)? {
+        return Err(AppError::RoomNotFound);
+    }
+
+    utils::join_room(room, &user.username, &db)?;
+
+    Ok("Success!".into())
+}
+
+async fn conversations(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let conversations = utils::get_conversations(&user.username, &db)?;
+
+    Ok(serde_json::to_string(&conversations)?)
+}
+
+async fn direct_messages(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(other): Path<String>,
+    Query(query): Query<types::PostsQuery>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    //The session user is always one side of the conversation, so nobody else can read it
+    let limit = query
+        .limit
+        .unwrap_or(types::DEFAULT_PAGE_SIZE)
+        .clamp(1, types::MAX_PAGE_SIZE);
+
+    let messages = match (query.before, query.after) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest("Use either before or after, not both"))
+        }
+        (None, Some(after)) => {
+            utils::get_direct_messages_after(&user.username, &other, after, limit, &db)
+        }
+        (before, None) => utils::get_direct_messages(&user.username, &other, before, limit, &db),
+    }?;
+
+    Ok(serde_json::to_string(&messages)?)
+}
+
+async fn newdm(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    Path(other): Path<String>,
+    message::Message(message): message::Message,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    if other == user.username {
+        return Err(AppError::InvalidRecipient);
+    }
+
+    if !utils::user_exists(&other, &db)? {
+        return Err(AppError::UserNotFound);
+    }
+
+    //Shares the limit with posts, so flooding can't just move to DMs
+    state.post_limiter.check(&user.username, &message)?;
+
+    let message = types::InsertDirectMessage {
+        from: user.username,
+        to: other,
+        message,
+        time: utils::get_time(),
     };
 
-    let username = utils::get_username_from_session(&auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    utils::send_direct_message(&message, &db)?;
 
-    let authorized = utils::validate_session(&username, &auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    Ok("Success".into())
+}
 
-    if !authorized {
-        Err(StatusCode::UNAUTHORIZED)
-    } else {
-        let post = types::InsertPost {
-            user: username,
-            message: String::from_utf8_lossy(body.as_ref()).into(),
-            time: utils::get_formatted_time(),
-        };
+async fn ws(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    //Subscribe before upgrading so no post sent during the handshake is missed
+    let posts_rx = state.posts_tx.subscribe();
+
+    upgrade.on_upgrade(move |socket| stream_posts(socket, posts_rx, state, user.username))
+}
 
-        utils::send_message(&post, &db).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn stream_posts(
+    socket: WebSocket,
+    mut posts_rx: Receiver<types::Post>,
+    state: types::AppState,
+    username: String,
+) {
+    let (mut sender, mut receiver) = socket.split();
 
-        Ok("Success".into())
+    loop {
+        tokio::select! {
+            post = posts_rx.recv() => {
+                let post = match post {
+                    Ok(v) => v,
+                    //Client fell too far behind, carry on from the oldest post still buffered
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                let visible = state
+                    .pool
+                    .get()
+                    .ok()
+                    .and_then(|db| utils::can_access_room(&username, post.room, &db).ok())
+                    .unwrap_or(false);
+
+                if !visible {
+                    continue;
+                }
+
+                let json = match serde_json::to_string(&post) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+
+                if sender.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            msg = receiver.next() => {
+                //Anything the client sends is ignored, we only care about when it goes away
+                match msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => (),
+                }
+            }
+        }
     }
 }
 
 async fn logout(
     jar: CookieJar,
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<(CookieJar, String), AppError> {
+    let db = state.pool.get()?;
+
+    //Other devices stay logged in
+    utils::end_session(&user.session, &db)?;
+
+    Ok((
+        jar.remove(Cookie::build(types::AUTH_COOKIE).path("/"))
+            .remove(Cookie::build(types::CSRF_COOKIE).path("/")),
+        "Success!".into(),
+    ))
+}
+
+async fn sessions(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let sessions = utils::list_sessions(Some(&user.username), Some(&user.session), &db)?;
+
+    Ok(serde_json::to_string(&sessions)?)
+}
+
+async fn revoke_session(
+    user: auth::AuthUser,
     State(state): State<types::AppState>,
-) -> Result<(CookieJar, String), StatusCode> {
-    let db = state
-        .pool
-        .get()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Path(session_num): Path<u64>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
 
-    let auth_cookie = if let Some(cookie) = jar.get("Liberated-Chat-Auth") {
-        cookie.value().to_string()
+    let revoked = utils::revoke_session(&user.username, session_num, &db)?;
+
+    if revoked {
+        Ok("Success!".into())
     } else {
-        return Err(StatusCode::UNAUTHORIZED);
-    };
+        Err(AppError::SessionNotFound)
+    }
+}
+
+//Logs out every device except the one asking
+async fn revoke_other_sessions(
+    user: auth::AuthUser,
+    State(state): State<types::AppState>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
 
-    let username = utils::get_username_from_session(&auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    utils::revoke_other_sessions(&user.username, &user.session, &db)?;
 
-    let authorized = utils::validate_session(&username, &auth_cookie, &db)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    Ok("Success!".into())
+}
+
+//Moderators can only act on accounts below their own role, so they can't ban each other
+fn check_outranks(
+    moderator: &auth::AuthUser,
+    target: &str,
+    db: &rusqlite::Connection,
+) -> Result<(), AppError> {
+    let (role, _) = utils::get_role(target, db).map_err(|_| AppError::UserNotFound)?;
 
-    if !authorized {
-        Err(StatusCode::UNAUTHORIZED)
+    if moderator.role > role {
+        Ok(())
     } else {
-        utils::logout(&username, &db).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Err(AppError::Forbidden)
+    }
+}
+
+async fn mod_deletepost(
+    auth::Moderator(moderator): auth::Moderator,
+    State(state): State<types::AppState>,
+    Path(post_num): Path<u64>,
+    reason: Bytes,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let post = utils::get_post(post_num, &db).map_err(|_| AppError::PostNotFound)?;
 
-        Ok((
-            jar.remove(Cookie::from("Liberated-Chat-Auth")),
-            "Success!".into(),
-        ))
+    if post.deleted {
+        return Err(AppError::PostNotFound);
     }
+
+    let post = utils::delete_post(post_num, &db)?;
+
+    utils::log_moderation(
+        &moderator.username,
+        "delete_post",
+        &post_num.to_string(),
+        &String::from_utf8_lossy(reason.as_ref()),
+        &db,
+    )?;
+
+    _ = state.posts_tx.send(post.clone());
+    send_parent(&post, &state, &db)?;
+
+    Ok(serde_json::to_string(&post)?)
 }
 
-async fn handler_404() -> impl IntoResponse {
-    (
-        StatusCode::NOT_FOUND,
-        "Nothing to see here. Error 404 page not found.",
-    )
+async fn ban(
+    auth::Moderator(moderator): auth::Moderator,
+    State(state): State<types::AppState>,
+    Path(username): Path<String>,
+    reason: Bytes,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    check_outranks(&moderator, &username, &db)?;
+
+    utils::set_banned(&username, true, &db)?;
+    utils::log_moderation(
+        &moderator.username,
+        "ban",
+        &username,
+        &String::from_utf8_lossy(reason.as_ref()),
+        &db,
+    )?;
+
+    Ok("Success!".into())
+}
+
+async fn unban(
+    auth::Moderator(moderator): auth::Moderator,
+    State(state): State<types::AppState>,
+    Path(username): Path<String>,
+    reason: Bytes,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    check_outranks(&moderator, &username, &db)?;
+
+    utils::set_banned(&username, false, &db)?;
+    utils::log_moderation(
+        &moderator.username,
+        "unban",
+        &username,
+        &String::from_utf8_lossy(reason.as_ref()),
+        &db,
+    )?;
+
+    Ok("Success!".into())
+}
+
+async fn force_logout(
+    auth::Moderator(moderator): auth::Moderator,
+    State(state): State<types::AppState>,
+    Path(username): Path<String>,
+    reason: Bytes,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    check_outranks(&moderator, &username, &db)?;
+
+    utils::logout(&username, &db)?;
+    utils::log_moderation(
+        &moderator.username,
+        "logout",
+        &username,
+        &String::from_utf8_lossy(reason.as_ref()),
+        &db,
+    )?;
+
+    Ok("Success!".into())
+}
+
+//Body is the new role: user, moderator or admin
+async fn set_role(
+    auth::Admin(admin): auth::Admin,
+    State(state): State<types::AppState>,
+    Path(username): Path<String>,
+    body: Bytes,
+) -> Result<String, AppError> {
+    let role: types::Role = String::from_utf8_lossy(body.as_ref())
+        .trim()
+        .parse()
+        .map_err(|_| AppError::InvalidRole)?;
+
+    //Stops the last admin from locking everyone out by accident
+    if username == admin.username {
+        return Err(AppError::CannotChangeOwnRole);
+    }
+
+    let db = state.pool.get()?;
+
+    //Admins can't change each other's roles any more than moderators can ban each other
+    check_outranks(&admin, &username, &db)?;
+
+    utils::set_role(&username, role, &db)?;
+    utils::log_moderation(&admin.username, "set_role", &username, role.as_str(), &db)?;
+
+    Ok("Success!".into())
+}
+
+async fn moderation_log(
+    _: auth::Moderator,
+    State(state): State<types::AppState>,
+    Query(query): Query<types::PostsQuery>,
+) -> Result<String, AppError> {
+    let db = state.pool.get()?;
+
+    let limit = query
+        .limit
+        .unwrap_or(types::DEFAULT_PAGE_SIZE)
+        .clamp(1, types::MAX_PAGE_SIZE);
+
+    let log = utils::get_moderation_log(limit, &db)?;
+
+    Ok(serde_json::to_string(&log)?)
+}
+
+//Every request made with a live session pushes its expiry back, and the cookies are sent back
+//out with a Max-Age to match
+async fn renew_session(
+    State(state): State<types::AppState>,
+    jar: CookieJar,
+    req: Request,
+    next: Next,
+) -> Response {
+    let renewed = jar.get(types::AUTH_COOKIE).and_then(|cookie| {
+        let db = state.pool.get().ok()?;
+        let lifetime = utils::renew_session(cookie.value(), &state.sessions, &db)
+            .ok()
+            .flatten()?;
+
+        //Sessions from before CSRF tokens existed get one here
+        let csrf_token = jar
+            .get(types::CSRF_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        Some([
+            session_cookie(cookie.value().to_string(), lifetime, &state.cookies),
+            csrf_cookie(csrf_token, lifetime, &state.cookies),
+        ])
+    });
+
+    let mut response = next.run(req).await;
+
+    //Handlers that set the cookies themselves, like login and logout, take priority
+    if let Some(cookies) = renewed {
+        if !response.headers().contains_key(header::SET_COOKIE) {
+            for cookie in cookies {
+                if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                    response.headers_mut().append(header::SET_COOKIE, value);
+                }
+            }
+        }
+    }
+
+    response
+}
+
+//Anything that isn't a plain read and comes with a session cookie has to echo the CSRF cookie
+//back in a header, which other sites can't do because they can't read it.
+//Logging in and registering don't use the session, and a stale cookie shouldn't block them
+async fn check_csrf(jar: CookieJar, req: Request, next: Next) -> Result<Response, AppError> {
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || matches!(req.uri().path(), "/login" | "/register");
+
+    if !safe && jar.get(types::AUTH_COOKIE).is_some() {
+        let cookie = jar.get(types::CSRF_COOKIE).map(|cookie| cookie.value());
+        let header = req
+            .headers()
+            .get(types::CSRF_HEADER)
+            .and_then(|v| v.to_str().ok());
+
+        match (cookie, header) {
+            (Some(cookie), Some(header)) if !cookie.is_empty() && cookie == header => (),
+            _ => return Err(AppError::CsrfFailed),
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+//Expired sessions are never used again, so they are cleared out every so often
+async fn purge_sessions(state: types::AppState) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(state.sessions.purge_interval.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        if let Ok(db) = state.pool.get() {
+            if let Err(e) = utils::purge_expired_sessions(&db) {
+                tracing::error!("Failed to purge expired sessions: {e}");
+            }
+
+            if let Err(e) = utils::purge_login_failures(&state.login_limits, &db) {
+                tracing::error!("Failed to purge old login failures: {e}");
+            }
+
+            if let Err(e) = state.attachments.purge_unused(&db) {
+                tracing::error!("Failed to purge unused attachments: {e:?}");
+            }
+        }
+
+        state.post_limiter.prune();
+    }
+}
+
+async fn handler_404() -> AppError {
+    AppError::NotFound
 }
 
 #[tokio::main]
@@ -222,17 +1191,62 @@ async fn main() {
         .route("/login", post(login))
         .route("/register", post(register))
         .route("/posts", get(posts))
+        .route("/posts/stream", get(posts_stream))
+        .route("/posts/:post_num", patch(editpost).delete(deletepost))
+        .route("/posts/:post_num/reply", post(reply))
+        .route("/posts/:post_num/thread", get(thread))
+        .route(
+            "/posts/:post_num/reactions/:emoji",
+            put(react).delete(unreact),
+        )
         .route("/newpost", post(newpost))
+        .route("/rooms", get(rooms).post(newroom))
+        .route("/rooms/:room/join", post(join))
+        .route("/rooms/:room/posts", get(room_posts))
+        .route("/rooms/:room/newpost", post(room_newpost))
+        .route(
+            "/rooms/:room/upload",
+            post(room_upload).layer(DefaultBodyLimit::max(
+                state.attachments.config.max_upload_size(&state.messages),
+            )),
+        )
+        .route("/attachments/:attachment_num", get(attachment))
         .route("/logout", post(logout))
+        .route("/me", get(me))
+        .route("/search", get(search))
+        .route("/notifications", get(notifications))
+        .route("/notifications/read", post(read_notifications))
+        .route("/notifications/:mention_num/read", post(read_notification))
+        .route("/limits", get(limits))
+        .route("/sessions", get(sessions).delete(revoke_other_sessions))
+        .route("/sessions/:session_num", delete(revoke_session))
+        .route("/dm", get(conversations))
+        .route("/dm/:username", get(direct_messages).post(newdm))
+        .route("/ws", get(ws))
+        .route("/mod/posts/:post_num", delete(mod_deletepost))
+        .route("/mod/users/:username/ban", post(ban))
+        .route("/mod/users/:username/unban", post(unban))
+        .route("/mod/users/:username/logout", post(force_logout))
+        .route("/mod/users/:username/role", put(set_role))
+        .route("/mod/log", get(moderation_log))
+        .route_layer(middleware::from_fn_with_state(state.clone(), renew_session))
+        .route_layer(middleware::from_fn(check_csrf))
         .nest_service("/", ServeDir::new(&frontend_path))
         .fallback(handler_404)
-        .with_state(state)
+        .with_state(state.clone())
+        .layer(DefaultBodyLimit::max(state.messages.max_body_size))
         .layer(CompressionLayer::new());
 
+    tokio::spawn(purge_sessions(state));
+
     let listener = tokio::net::TcpListener::bind(("localhost", port))
         .await
         .unwrap();
 
-    axum::serve(listener, routes).await.unwrap();
+    axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
-
@@ -0,0 +1,79 @@
+use super::{policy, types, utils};
+
+//Most people one post can notify, mentions past this are left as plain text
+pub const MAX_MENTIONS_PER_POST: usize = 20;
+//Newest notifications GET /notifications returns
+pub const MAX_NOTIFICATIONS: u64 = 50;
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || policy::USERNAME_SYMBOLS.contains(&c)
+}
+
+//Every @name in the message as a username key (see policy::username_key), each only once.
+//An @ straight after a name character is part of something else, like an email address
+pub fn parse(message: &str) -> Vec<String> {
+    let chars: Vec<char> = message.chars().collect();
+    let mut keys = Vec::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c != '@' || (i > 0 && is_name_char(chars[i - 1])) {
+            continue;
+        }
+
+        let name: String = chars[i + 1..]
+            .iter()
+            .take_while(|&&c| is_name_char(c))
+            .collect();
+
+        //A full stop right after a name is almost always the end of the sentence
+        let name = name.trim_end_matches('.');
+
+        if name.is_empty() {
+            continue;
+        }
+
+        let key = policy::username_key(name);
+
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
+//Notifies everyone mentioned in the post who can see it, other than whoever wrote it.
+//Being mentioned again in the same post, say after an edit, doesn't notify anyone twice
+pub fn record(post: &types::Post, db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    for key in parse(&post.message).iter().take(MAX_MENTIONS_PER_POST) {
+        let Some(username) = utils::find_username(key, db)? else {
+            continue;
+        };
+
+        if username != post.user && utils::can_access_room(&username, post.room, db)? {
+            utils::add_mention(post.post_num, &username, db)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("@alice hi"), vec!["alice"]);
+        assert_eq!(parse("hi @Alice and @bob."), vec!["alice", "bob"]);
+        assert_eq!(
+            parse("(@john_doe), @ALICE @alice"),
+            vec!["john_doe", "alice"]
+        );
+        //Fullwidth letters fold into plain ones, same as at registration
+        assert_eq!(parse("@ａｌｉｃｅ"), vec!["alice"]);
+
+        assert!(parse("mail me at alice@example.com").is_empty());
+        assert!(parse("@ alone, @@ and @.").is_empty());
+    }
+}
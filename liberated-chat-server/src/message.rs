@@ -0,0 +1,59 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+};
+use serde::Serialize;
+
+use super::{error::AppError, types, utils};
+
+//Limits on what can be posted. Sent to clients as-is by GET /limits
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct MessageConfig {
+    //In characters
+    pub max_message_length: usize,
+    //In bytes, for any request at all
+    pub max_body_size: usize,
+}
+
+impl MessageConfig {
+    //MAX_MESSAGE_LENGTH and MAX_BODY_SIZE
+    pub fn from_env() -> Self {
+        Self {
+            max_message_length: utils::env_or("MAX_MESSAGE_LENGTH", 2000),
+            max_body_size: utils::env_or("MAX_BODY_SIZE", 64 * 1024),
+        }
+    }
+}
+
+//Body of a post or DM. Rejects bodies that are blank or longer than max_message_length
+pub struct Message(pub String);
+
+#[async_trait]
+impl FromRequest<types::AppState> for Message {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &types::AppState) -> Result<Self, Self::Rejection> {
+        //Only fails on bodies over max_body_size, see DefaultBodyLimit in main
+        let body = Bytes::from_request(req, state).await.map_err(|e| {
+            if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                AppError::PayloadTooLarge
+            } else {
+                AppError::BadRequest("Failed to read the message")
+            }
+        })?;
+
+        let message = String::from_utf8_lossy(&body).into_owned();
+
+        if message.trim().is_empty() {
+            return Err(AppError::EmptyMessage);
+        }
+
+        if message.chars().count() > state.messages.max_message_length {
+            return Err(AppError::MessageTooLong(state.messages.max_message_length));
+        }
+
+        Ok(Self(message))
+    }
+}
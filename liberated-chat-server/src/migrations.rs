@@ -0,0 +1,197 @@
+use rusqlite::params;
+use std::error::Error;
+
+use super::policy;
+
+//Applied in order, a database's PRAGMA user_version is how many of these it has had.
+//Only ever append to this list, and never change a migration once it has been released
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/001_initial.sql"),
+    include_str!("../migrations/002_sessions.sql"),
+    include_str!("../migrations/003_username_keys.sql"),
+    include_str!("../migrations/004_login_failures.sql"),
+    include_str!("../migrations/005_epoch_times.sql"),
+    include_str!("../migrations/006_attachments.sql"),
+    include_str!("../migrations/007_reactions.sql"),
+    include_str!("../migrations/008_threads.sql"),
+    include_str!("../migrations/009_mentions.sql"),
+    include_str!("../migrations/010_search.sql"),
+];
+
+//Brings the database up to the latest schema.
+//Errors without touching anything if the database was made by a newer build
+pub fn run(db: &mut rusqlite::Connection) -> Result<(), Box<dyn Error>> {
+    let mut version = get_version(db)?;
+
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Database is at schema version {version}, but this build only knows up to {}",
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    if version == 0 && table_exists("users", db)? {
+        adopt_unversioned(db)?;
+        version = get_version(db)?;
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = db.transaction()?;
+
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+
+        tx.commit()?;
+    }
+
+    fill_username_keys(db)?;
+
+    Ok(())
+}
+
+//Keys are computed in Rust, so users from before 003 get theirs here. Users whose keys clash
+//with someone else's keep none rather than stop the server from starting
+fn fill_username_keys(db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let mut stmt = db.prepare("SELECT username FROM users WHERE usernameKey IS NULL;")?;
+
+    let usernames = stmt
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for username in usernames {
+        let result = db.execute(
+            "UPDATE users SET usernameKey = ? WHERE username = ?;",
+            params![policy::username_key(&username), username],
+        );
+
+        match result {
+            Ok(_) => {}
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn get_version(db: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    db.query_row("PRAGMA user_version;", params![], |row| row.get(0))
+}
+
+fn table_exists(table: &str, db: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+    let mut stmt = db.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?;")?;
+    stmt.exists(params![table])
+}
+
+//Databases from before versioning had new columns patched in at startup, so they can be missing
+//any of them. Patch them one last time, after which they match the first migration
+fn adopt_unversioned(db: &mut rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let tx = db.transaction()?;
+
+    //Posts from before rooms existed all belong to the general room
+    add_missing_column("posts", "roomId", "INTEGER NOT NULL DEFAULT 1", &tx)?;
+    add_missing_column("posts", "editedAt", "TEXT", &tx)?;
+    add_missing_column("posts", "deleted", "INTEGER NOT NULL DEFAULT 0", &tx)?;
+    add_missing_column("users", "role", "TEXT NOT NULL DEFAULT 'user'", &tx)?;
+    add_missing_column("users", "banned", "INTEGER NOT NULL DEFAULT 0", &tx)?;
+
+    tx.execute_batch(MIGRATIONS[0])?;
+    tx.pragma_update(None, "user_version", 1)?;
+
+    tx.commit()
+}
+
+fn add_missing_column(
+    table: &str,
+    column: &str,
+    definition: &str,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = db.prepare("SELECT 1 FROM pragma_table_info(?) WHERE name = ?;")?;
+
+    if !stmt.exists(params![table, column])? {
+        db.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition};"
+        ))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_database() {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+
+        run(&mut db).unwrap();
+        assert_eq!(get_version(&db).unwrap(), MIGRATIONS.len());
+        assert!(table_exists("posts", &db).unwrap());
+
+        //Running again is a no-op
+        run(&mut db).unwrap();
+        assert_eq!(get_version(&db).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_unversioned_database() {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+
+        //The schema from before rooms, edits or roles
+        db.execute_batch(
+            "CREATE TABLE users (
+                        username TEXT NOT NULL UNIQUE,
+                        password TEXT NOT NULL
+                    ) STRICT;
+                    CREATE TABLE posts (
+                        postNum INTEGER PRIMARY KEY AUTOINCREMENT,
+                        username TEXT NOT NULL,
+                        message TEXT NOT NULL,
+                        time TEXT NOT NULL
+                    ) STRICT;
+                    INSERT INTO users VALUES ('john', 'hash'), ('John', 'hash');
+                    INSERT INTO posts (username, message, time) VALUES ('john', 'hi', '01/31/2024 @ 10:00');",
+        )
+        .unwrap();
+
+        run(&mut db).unwrap();
+        assert_eq!(get_version(&db).unwrap(), MIGRATIONS.len());
+
+        let (room, time): (u64, u64) = db
+            .query_row(
+                "SELECT roomId, time FROM posts WHERE postNum = 1;",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(room, 1);
+        //Old text times become Unix timestamps
+        assert_eq!(time, 1706695200);
+
+        //Existing look-alikes both keep working, but only one of them gets the key
+        let keys: u64 = db
+            .query_row(
+                "SELECT count(*) FROM users WHERE usernameKey = 'john';",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(keys, 1);
+    }
+
+    #[test]
+    fn test_newer_database() {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+
+        db.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(run(&mut db).is_err());
+        //Nothing was created
+        assert!(!table_exists("users", &db).unwrap());
+    }
+}
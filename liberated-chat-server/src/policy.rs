@@ -0,0 +1,209 @@
+use std::{collections::HashSet, env, fs, sync::Arc};
+use unicode_normalization::UnicodeNormalization;
+
+use super::utils;
+
+//Characters allowed in usernames besides letters and digits
+pub(crate) const USERNAME_SYMBOLS: &[char] = &['_', '-', '.'];
+
+//What register accepts for usernames and passwords
+#[derive(Clone, Debug)]
+pub struct Policy {
+    //Lengths are in characters, after normalization
+    pub username_min_length: usize,
+    pub username_max_length: usize,
+    //Letters and digits from any script, rather than just ASCII ones
+    pub unicode_usernames: bool,
+    pub password_min_length: usize,
+    //Hashing is slow on purpose, so this keeps huge passwords from tying up the server
+    pub password_max_length: usize,
+    //How many of lowercase, uppercase, digits and everything else a password has to mix
+    pub password_min_classes: usize,
+    //Lowercased passwords from known breaches, refused however strong they look
+    pub breached_passwords: Arc<HashSet<String>>,
+}
+
+impl Policy {
+    //USERNAME_MIN_LENGTH, USERNAME_MAX_LENGTH, UNICODE_USERNAMES, PASSWORD_MIN_LENGTH,
+    //PASSWORD_MAX_LENGTH, PASSWORD_MIN_CLASSES and BREACHED_PASSWORDS_FILE, all optional
+    pub fn from_env() -> Self {
+        let unicode_usernames = match env::var("UNICODE_USERNAMES") {
+            Ok(v) => v.parse().expect("UNICODE_USERNAMES must be true or false!"),
+            Err(_) => true,
+        };
+
+        //One password per line, the usual format for published lists
+        let breached_passwords = match env::var("BREACHED_PASSWORDS_FILE") {
+            Ok(path) if !path.is_empty() => fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read BREACHED_PASSWORDS_FILE {path}: {e}"))
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty())
+                .collect(),
+            _ => HashSet::new(),
+        };
+
+        Self {
+            username_min_length: utils::env_or("USERNAME_MIN_LENGTH", 3),
+            username_max_length: utils::env_or("USERNAME_MAX_LENGTH", 32),
+            unicode_usernames,
+            password_min_length: utils::env_or("PASSWORD_MIN_LENGTH", 8),
+            password_max_length: utils::env_or("PASSWORD_MAX_LENGTH", 256),
+            password_min_classes: utils::env_or("PASSWORD_MIN_CLASSES", 2),
+            breached_passwords: Arc::new(breached_passwords),
+        }
+    }
+
+    //Gives back the username as it should be stored, or why it isn't allowed
+    pub fn check_username(&self, username: &str) -> Result<String, String> {
+        let username = normalize(username);
+        let length = username.chars().count();
+
+        if length < self.username_min_length || length > self.username_max_length {
+            return Err(format!(
+                "Usernames must be between {} and {} characters",
+                self.username_min_length, self.username_max_length
+            ));
+        }
+
+        let allowed = |c: char| {
+            USERNAME_SYMBOLS.contains(&c)
+                || if self.unicode_usernames {
+                    c.is_alphanumeric()
+                } else {
+                    c.is_ascii_alphanumeric()
+                }
+        };
+
+        if !username.chars().all(allowed) {
+            return Err(if self.unicode_usernames {
+                "Usernames may only contain letters, digits, _, - and .".into()
+            } else {
+                "Usernames may only contain a-z, A-Z, 0-9, _, - and .".into()
+            });
+        }
+
+        Ok(username)
+    }
+
+    //Why the password isn't good enough, if it isn't. Takes the already checked username
+    pub fn check_password(&self, password: &str, username: &str) -> Result<(), String> {
+        let length = password.chars().count();
+
+        if length < self.password_min_length {
+            return Err(format!(
+                "Passwords must be at least {} characters",
+                self.password_min_length
+            ));
+        }
+
+        if length > self.password_max_length {
+            return Err(format!(
+                "Passwords can't be longer than {} characters",
+                self.password_max_length
+            ));
+        }
+
+        if character_classes(password) < self.password_min_classes {
+            return Err(format!(
+                "Passwords must mix at least {} of lowercase letters, uppercase letters, digits and symbols",
+                self.password_min_classes
+            ));
+        }
+
+        let lowercase = password.to_lowercase();
+
+        if lowercase.contains(&username.to_lowercase()) {
+            return Err("Passwords can't contain the username".into());
+        }
+
+        if self.breached_passwords.contains(&lowercase) {
+            return Err("This password has appeared in a data breach, pick another".into());
+        }
+
+        Ok(())
+    }
+}
+
+//Folds compatibility characters (fullwidth letters, ligatures, ...) into their plain forms
+pub fn normalize(username: &str) -> String {
+    username.nfkc().collect()
+}
+
+//Two usernames with the same key count as the same name, so "Alice" can't pose as "alice"
+pub fn username_key(username: &str) -> String {
+    normalize(username).to_lowercase()
+}
+
+fn character_classes(password: &str) -> usize {
+    let has = |f: fn(&char) -> bool| password.chars().any(|c| f(&c)) as usize;
+
+    has(|c| c.is_lowercase())
+        + has(|c| c.is_uppercase())
+        + has(|c| c.is_numeric())
+        + has(|c| !c.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        Policy {
+            username_min_length: 3,
+            username_max_length: 8,
+            unicode_usernames: true,
+            password_min_length: 8,
+            password_max_length: 16,
+            password_min_classes: 2,
+            breached_passwords: Arc::new(HashSet::from(["password1".to_string()])),
+        }
+    }
+
+    #[test]
+    fn test_check_username() {
+        let policy = policy();
+
+        assert_eq!(policy.check_username("john").unwrap(), "john");
+        assert_eq!(policy.check_username("Jöhn_2").unwrap(), "Jöhn_2");
+        //Fullwidth letters are folded to ASCII
+        assert_eq!(policy.check_username("ｊｏｈｎ").unwrap(), "john");
+
+        assert!(policy.check_username("jo").is_err());
+        assert!(policy.check_username("johnjohnjohn").is_err());
+        assert!(policy.check_username("jo hn").is_err());
+        assert!(policy.check_username("jo\u{0}hn").is_err());
+        assert!(policy.check_username("john\u{200b}").is_err());
+
+        let ascii_only = Policy {
+            unicode_usernames: false,
+            ..policy
+        };
+
+        assert!(ascii_only.check_username("Jöhn").is_err());
+    }
+
+    #[test]
+    fn test_check_password() {
+        let policy = policy();
+
+        assert!(policy.check_password("correct horse", "john").is_ok());
+        assert!(policy.check_password("pässwörd", "john").is_err());
+        assert!(policy.check_password("Pässwörd", "john").is_ok());
+
+        assert!(policy.check_password("Short1", "john").is_err());
+        assert!(policy
+            .check_password("Far too long of a password", "john")
+            .is_err());
+        assert!(policy.check_password("alllowercase", "john").is_err());
+        assert!(policy.check_password("John's password", "john").is_err());
+        assert!(policy.check_password("PASSWORD1", "john").is_err());
+    }
+
+    #[test]
+    fn test_username_key() {
+        assert_eq!(username_key("Alice"), username_key("alice"));
+        assert_eq!(username_key("ＡＬＩＣＥ"), username_key("alice"));
+        assert_ne!(username_key("alice"), username_key("alice2"));
+    }
+}
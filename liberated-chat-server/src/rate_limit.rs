@@ -0,0 +1,171 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::utils;
+
+//How fast users may post, as a token bucket: a full bucket holds burst posts and refills at
+//per_minute posts a minute. Repeating your last message within duplicate_window seconds is refused
+#[derive(Clone, Copy, Debug)]
+pub struct PostLimitConfig {
+    pub burst: u64,
+    pub per_minute: u64,
+    pub duplicate_window: u64,
+}
+
+impl PostLimitConfig {
+    pub fn from_env() -> Self {
+        Self {
+            burst: utils::env_or("POST_BURST", 5),
+            per_minute: utils::env_or("POST_RATE_PER_MINUTE", 30),
+            duplicate_window: utils::env_or("POST_DUPLICATE_WINDOW", 30),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Limited {
+    //Out of tokens, holds how many seconds until the next one
+    TooFast(u64),
+    Duplicate,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    last_message: u64,
+    last_message_at: Instant,
+}
+
+//Buckets live in memory only, a restart gives everyone a full one
+#[derive(Clone)]
+pub struct PostLimiter {
+    config: PostLimitConfig,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl PostLimiter {
+    pub fn new(config: PostLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    //Spends one of username's tokens on message, unless they have none left or just sent it
+    pub fn check(&self, username: &str, message: &str) -> Result<(), Limited> {
+        self.check_at(username, message, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, message: &str, now: Instant) -> Result<(), Limited> {
+        let burst = self.config.burst.max(1) as f64;
+        let per_second = self.config.per_minute as f64 / 60.0;
+
+        let mut hasher = DefaultHasher::new();
+        message.trim().hash(&mut hasher);
+        let message = hasher.finish();
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let bucket = buckets.entry(username.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+            last_message: !message,
+            last_message_at: now,
+        });
+
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * per_second)
+            .min(burst);
+        bucket.updated = now;
+
+        if bucket.last_message == message
+            && now.duration_since(bucket.last_message_at)
+                < Duration::from_secs(self.config.duplicate_window)
+        {
+            return Err(Limited::Duplicate);
+        }
+
+        if bucket.tokens < 1.0 {
+            let wait = if per_second > 0.0 {
+                ((1.0 - bucket.tokens) / per_second).ceil() as u64
+            } else {
+                u64::MAX
+            };
+
+            return Err(Limited::TooFast(wait.max(1)));
+        }
+
+        bucket.tokens -= 1.0;
+        bucket.last_message = message;
+        bucket.last_message_at = now;
+
+        Ok(())
+    }
+
+    //Drops buckets that have refilled and are past the duplicate window, which behave like new ones
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let burst = self.config.burst.max(1) as f64;
+        let per_second = self.config.per_minute as f64 / 60.0;
+        let window = Duration::from_secs(self.config.duplicate_window);
+
+        self.buckets.lock().unwrap().retain(|_, bucket| {
+            bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second < burst
+                || now.duration_since(bucket.last_message_at) < window
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: PostLimitConfig = PostLimitConfig {
+        burst: 2,
+        per_minute: 60,
+        duplicate_window: 10,
+    };
+
+    #[test]
+    fn test_burst_and_refill() {
+        let limiter = PostLimiter::new(CONFIG);
+        let start = Instant::now();
+
+        assert!(limiter.check_at("john", "one", start).is_ok());
+        assert!(limiter.check_at("john", "two", start).is_ok());
+        assert_eq!(
+            limiter.check_at("john", "three", start),
+            Err(Limited::TooFast(1))
+        );
+
+        //Other users have their own bucket
+        assert!(limiter.check_at("jack", "one", start).is_ok());
+
+        //One token a second
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.check_at("john", "three", later).is_ok());
+        assert!(limiter.check_at("john", "four", later).is_err());
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let limiter = PostLimiter::new(CONFIG);
+        let start = Instant::now();
+
+        assert!(limiter.check_at("john", "spam", start).is_ok());
+        assert_eq!(
+            limiter.check_at("john", " spam ", start + Duration::from_secs(5)),
+            Err(Limited::Duplicate)
+        );
+        assert!(limiter
+            .check_at("john", "spam", start + Duration::from_secs(11))
+            .is_ok());
+
+        //Someone else saying the same thing is fine
+        assert!(limiter.check_at("jack", "spam", start).is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+//Most different emoji one user can react to a single post with
+pub const MAX_REACTIONS_PER_USER: usize = 10;
+
+//Most characters a reaction can be made of, enough for flags, skin tones and ZWJ sequences
+const MAX_EMOJI_LENGTH: usize = 16;
+
+//Characters that are emoji on their own
+fn is_pictographic(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x00a9
+            | 0x00ae
+            | 0x203c
+            | 0x2049
+            | 0x2122
+            | 0x2139
+            | 0x2194..=0x21aa
+            | 0x231a..=0x23ff
+            | 0x24c2
+            | 0x25aa..=0x27bf
+            | 0x2934
+            | 0x2935
+            | 0x2b05..=0x2b55
+            | 0x3030
+            | 0x303d
+            | 0x3297
+            | 0x3299
+            | 0x1f000..=0x1faff
+    )
+}
+
+//Characters that only change or join up the emoji around them. Digits, # and * only turn into
+//emoji as part of a keycap
+fn is_modifier(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200d}' | '\u{fe0e}' | '\u{fe0f}' | '\u{20e3}' | '\u{e0020}'..='\u{e007f}'
+    ) || c.is_ascii_digit()
+        || c == '#'
+        || c == '*'
+}
+
+//Whether the reaction is made of emoji and nothing else
+pub fn is_emoji(emoji: &str) -> bool {
+    let length = emoji.chars().count();
+
+    (1..=MAX_EMOJI_LENGTH).contains(&length)
+        && emoji.chars().any(|c| is_pictographic(c) || c == '\u{20e3}')
+        && emoji.chars().all(|c| is_pictographic(c) || is_modifier(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_emoji() {
+        assert!(is_emoji("👍"));
+        assert!(is_emoji("❤️"));
+        assert!(is_emoji("👍🏽"));
+        //Flags, keycaps and ZWJ sequences are several characters
+        assert!(is_emoji("🇳🇱"));
+        assert!(is_emoji("1️⃣"));
+        assert!(is_emoji("👩‍💻"));
+
+        assert!(!is_emoji(""));
+        assert!(!is_emoji("+1"));
+        assert!(!is_emoji("1"));
+        assert!(!is_emoji("👍 "));
+        assert!(!is_emoji("<b>👍</b>"));
+        assert!(!is_emoji(&"👍".repeat(17)));
+    }
+}
@@ -0,0 +1,109 @@
+use super::types;
+
+//Results GET /search returns when the client does not ask for a number
+pub const DEFAULT_RESULTS: u64 = 20;
+pub const MAX_RESULTS: u64 = 50;
+//Longest search accepted, in characters
+pub const MAX_QUERY_LENGTH: usize = 256;
+
+//Put around matches in snippets by SQLite, then split out by split_snippet. Control characters,
+//so they can't be confused with anything someone typed
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+//Turns what was typed into an FTS5 query for posts containing every word, each word also matching
+//longer ones it starts. Words are quoted so none of FTS5's own syntax gets through.
+//None if there is nothing to search for
+pub fn to_match_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        //Punctuation on its own is never indexed, and would leave an empty phrase behind
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+//YYYY-MM-DD, the only format dates are taken in. SQLite's unixepoch matches nothing otherwise
+pub fn is_date(date: &str) -> bool {
+    let bytes = date.as_bytes();
+
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, byte)| match i {
+            4 | 7 => *byte == b'-',
+            _ => byte.is_ascii_digit(),
+        })
+}
+
+//Breaks a snippet from SQLite up at the highlight markers, so clients never have to deal in HTML
+pub fn split_snippet(snippet: &str) -> Vec<types::SnippetPart> {
+    let mut parts = Vec::new();
+    let mut highlight = false;
+    let mut text = String::new();
+
+    for c in snippet.chars() {
+        if c == HIGHLIGHT_START || c == HIGHLIGHT_END {
+            if !text.is_empty() {
+                parts.push(types::SnippetPart {
+                    text: std::mem::take(&mut text),
+                    highlight,
+                });
+            }
+
+            highlight = c == HIGHLIGHT_START;
+        } else {
+            text.push(c);
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(types::SnippetPart { text, highlight });
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_match_query() {
+        assert_eq!(to_match_query("hello").unwrap(), "\"hello\"*");
+        assert_eq!(
+            to_match_query("  rust   async ").unwrap(),
+            "\"rust\"* \"async\"*"
+        );
+        //FTS5 syntax is searched for as text
+        assert_eq!(
+            to_match_query("a\"b OR NOT c*").unwrap(),
+            "\"a\"\"b\"* \"OR\"* \"NOT\"* \"c*\"*"
+        );
+        assert!(to_match_query("").is_none());
+        assert!(to_match_query(" - ** ").is_none());
+    }
+
+    #[test]
+    fn test_split_snippet() {
+        let parts = split_snippet("say \u{2}hello\u{3} to \u{2}everyone\u{3}");
+
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| (part.text.as_str(), part.highlight))
+                .collect::<Vec<_>>(),
+            vec![
+                ("say ", false),
+                ("hello", true),
+                (" to ", false),
+                ("everyone", true)
+            ]
+        );
+        assert!(split_snippet("").is_empty());
+
+        assert!(is_date("2024-01-31"));
+        assert!(!is_date("2024-1-31"));
+        assert!(!is_date("31-01-2024"));
+    }
+}
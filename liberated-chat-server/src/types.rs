@@ -1,42 +1,409 @@
+use axum_extra::extract::cookie::SameSite;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{env, fmt, str::FromStr};
+
+use super::{
+    attachment::{AttachmentConfig, AttachmentStore},
+    message::MessageConfig,
+    migrations,
+    policy::Policy,
+    rate_limit::{PostLimitConfig, PostLimiter},
+    utils,
+};
+use tokio::sync::broadcast;
+
+//Room every user is in, and where the original /posts and /newpost routes point
+pub const GENERAL_ROOM: u64 = 1;
+//Longest room name allowed, in characters
+pub const MAX_ROOM_NAME_LENGTH: usize = 64;
+
+//Page size used by GET /posts when the client does not ask for one
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+//Largest page GET /posts will ever return, no matter what the client asks for
+pub const MAX_PAGE_SIZE: u64 = 200;
+
+//Holds the session id, never readable from scripts
+pub const AUTH_COOKIE: &str = "Liberated-Chat-Auth";
+//Double-submit CSRF token. Scripts read it and echo it back in CSRF_HEADER
+pub const CSRF_COOKIE: &str = "Liberated-Chat-CSRF";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+//How many unread posts a slow websocket client may lag behind before it is skipped ahead
+const BROADCAST_CAPACITY: usize = 256;
+
+//How long sessions last, all in seconds
+#[derive(Clone, Copy, Debug)]
+pub struct SessionConfig {
+    //Unused sessions expire this long after they were last used
+    pub idle_timeout: u64,
+    //No session outlives this, however active it is
+    pub max_age: u64,
+    //How often expired sessions are swept out of the database
+    pub purge_interval: u64,
+}
+
+impl SessionConfig {
+    //Falls back to the old fixed two days for anything unset, so older .env files still work
+    pub fn from_env() -> Self {
+        Self {
+            idle_timeout: utils::env_or("SESSION_IDLE_TIMEOUT", 2 * 24 * 60 * 60),
+            max_age: utils::env_or("SESSION_MAX_AGE", 30 * 24 * 60 * 60),
+            purge_interval: utils::env_or("SESSION_PURGE_INTERVAL", 60 * 60),
+        }
+    }
+}
+
+//Limits on failed logins, tracked per username and per IP. Every failure locks the login out for
+//twice as long as the one before, up to backoff_max seconds, and max_failures of them in a row
+//lock it out for lockout seconds. Failures are forgotten after lockout seconds without one
+#[derive(Clone, Copy, Debug)]
+pub struct LoginLimitConfig {
+    pub max_failures: u64,
+    //Higher, since many people can share an IP
+    pub max_failures_per_ip: u64,
+    pub backoff_max: u64,
+    pub lockout: u64,
+}
+
+impl LoginLimitConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_failures: utils::env_or("LOGIN_MAX_FAILURES", 5),
+            max_failures_per_ip: utils::env_or("LOGIN_MAX_FAILURES_PER_IP", 20),
+            backoff_max: utils::env_or("LOGIN_BACKOFF_MAX", 60),
+            lockout: utils::env_or("LOGIN_LOCKOUT", 15 * 60),
+        }
+    }
+}
+
+//Attributes put on every cookie the server sets
+#[derive(Clone, Copy, Debug)]
+pub struct CookieConfig {
+    //Only send cookies over https. Needs the site to be served over https to log in at all
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl CookieConfig {
+    //COOKIE_SECURE (true or false) and COOKIE_SAME_SITE (strict, lax or none).
+    //Defaults suit plain http on localhost, so older .env files still work
+    pub fn from_env() -> Self {
+        let secure = match env::var("COOKIE_SECURE") {
+            Ok(v) => v.parse().expect("COOKIE_SECURE must be true or false!"),
+            Err(_) => false,
+        };
+
+        let same_site = match env::var("COOKIE_SAME_SITE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "strict" => SameSite::Strict,
+            "lax" | "" => SameSite::Lax,
+            "none" => SameSite::None,
+            _ => panic!("COOKIE_SAME_SITE must be strict, lax or none!"),
+        };
+
+        Self { secure, same_site }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     //Mutex is best practice for a simple sqlite3 db
     pub pool: Pool<SqliteConnectionManager>,
+    //Every post inserted is sent here so live clients can pick it up
+    pub posts_tx: broadcast::Sender<Post>,
+    pub sessions: SessionConfig,
+    pub cookies: CookieConfig,
+    pub policy: Policy,
+    pub login_limits: LoginLimitConfig,
+    pub post_limiter: PostLimiter,
+    pub messages: MessageConfig,
+    pub attachments: AttachmentStore,
+    //Deprecated: also accept credentials from Username and Password headers
+    pub allow_header_credentials: bool,
+    //Header a reverse proxy puts the client's IP in, see auth::ClientIp
+    pub client_ip_header: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Post {
     pub post_num: u64,
+    pub room: u64,
     pub user: String,
+    //Empty once the post has been deleted
     pub message: String,
+    //ISO 8601 in UTC, see utils::to_iso8601
     pub time: String,
+    pub edited_at: Option<String>,
+    pub deleted: bool,
+    //Dropped along with the message when the post is deleted
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    //In the order they were first used
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    //The post that started the thread this is a reply to
+    #[serde(default)]
+    pub parent_post_num: Option<u64>,
+    //Replies that haven't been deleted, always 0 for replies themselves
+    #[serde(default)]
+    pub reply_count: u64,
+}
+
+//A post and every reply to it, oldest first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Thread {
+    pub post: Post,
+    pub replies: Vec<Post>,
+}
+
+//Everyone who reacted to a post with the same emoji
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: u64,
+    pub users: Vec<String>,
+}
+
+//A file on a post, served from GET /attachments/<attachment_num>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub attachment_num: u64,
+    pub name: String,
+    pub mime: String,
+    //In bytes
+    pub size: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct InsertPost {
+    pub room: u64,
     pub user: String,
     pub message: String,
+    //Unix timestamp, in seconds
+    pub time: u64,
+    //Set for replies, see Post
+    pub parent_post_num: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DirectMessage {
+    pub dm_num: u64,
+    pub from: String,
+    pub to: String,
+    pub message: String,
+    pub time: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InsertDirectMessage {
+    pub from: String,
+    pub to: String,
+    pub message: String,
+    pub time: u64,
+}
+
+//Someone the user has exchanged direct messages with, and the latest of those messages
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub user: String,
+    pub last_message: DirectMessage,
+}
+
+//A post the user was mentioned in
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub mention_num: u64,
+    pub read: bool,
+    pub post: Post,
+}
+
+//Body of GET /notifications, newest first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notifications {
+    //Across every notification, not just the ones listed
+    pub unread: u64,
+    pub notifications: Vec<Notification>,
+}
+
+//Query string of GET /search. Everything but q is optional, dates are YYYY-MM-DD in UTC and
+//both ends are included
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub q: String,
+    pub user: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<u64>,
+}
+
+//A post matching a search, best matches first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub post: Post,
+    //The part of the message around the match, with the matching words marked
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Room {
+    pub room_id: u64,
+    pub name: String,
+    pub joined: bool,
+}
+
+//Ordered from least to most trusted, so roles can be compared directly
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role {s}")),
+        }
+    }
+}
+
+//One entry in the moderation audit log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModerationAction {
+    pub action_num: u64,
+    pub moderator: String,
+    pub action: String,
+    pub target: String,
+    pub reason: String,
     pub time: String,
 }
 
+//Rows and counts reported by liberated-chat-admin
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserInfo {
+    pub username: String,
+    pub role: Role,
+    pub banned: bool,
+}
+
+//One logged in device. The session id itself is never sent back out, session_num stands in for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub session_num: u64,
+    pub username: String,
+    //Unix timestamps, in seconds
+    pub created_at: u64,
+    pub last_seen: u64,
+    pub expiration: u64,
+    pub user_agent: String,
+    pub ip: String,
+    //Whether this is the session making the request
+    pub current: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DatabaseStats {
+    pub users: u64,
+    pub banned_users: u64,
+    pub sessions: u64,
+    pub rooms: u64,
+    pub posts: u64,
+    pub deleted_posts: u64,
+    pub direct_messages: u64,
+    pub size_bytes: u64,
+}
+
+//Body of /login and /register
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+//JSON body of every error response. `code` is stable, `message` is for people
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
+//Query parameters for paging through GET /posts, cursors are post numbers
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PostsQuery {
+    pub before: Option<u64>,
+    pub after: Option<u64>,
+    pub limit: Option<u64>,
+}
+
 impl AppState {
     pub fn new() -> Self {
+        let pool = Self::open_pool();
+
+        let (posts_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+
+        Self {
+            pool,
+            posts_tx,
+            sessions: SessionConfig::from_env(),
+            cookies: CookieConfig::from_env(),
+            policy: Policy::from_env(),
+            login_limits: LoginLimitConfig::from_env(),
+            post_limiter: PostLimiter::new(PostLimitConfig::from_env()),
+            messages: MessageConfig::from_env(),
+            attachments: AttachmentStore::new(AttachmentConfig::from_env()),
+            allow_header_credentials: env::var("ALLOW_HEADER_CREDENTIALS")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            client_ip_header: env::var("CLIENT_IP_HEADER")
+                .ok()
+                .filter(|name| !name.is_empty()),
+        }
+    }
+
+    //Opens DATABASE_PATH/DATABASE_NAME and brings the schema up to date.
+    //liberated-chat-admin goes through here too, so it never sees an older schema than the server
+    pub fn open_pool() -> Pool<SqliteConnectionManager> {
         let path = format!(
             "{}/{}",
             env::var("DATABASE_PATH").expect("Set DATABASE_PATH env variable!"),
             env::var("DATABASE_NAME").expect("Set DATABASE_NAME env variable!")
         );
-        let manager = SqliteConnectionManager::file(path);
-        let pool = Pool::new(manager).expect("Failed to open database file!");
-
-        pool.get()
-            .expect("Failed to access database!")
-            .execute_batch(
+        //These only last as long as the connection, so every pooled connection needs them
+        let manager = SqliteConnectionManager::file(path).with_init(|db| {
+            db.execute_batch(
                 "
                     PRAGMA journal_mode=WAL;
                     PRAGMA busy_timeout = 5000;
@@ -44,27 +411,23 @@ impl AppState {
                     PRAGMA cache_size = 1000000000;
                     PRAGMA foreign_keys = true;
                     PRAGMA temp_store = memory;
-                    CREATE TABLE IF NOT EXISTS users (
-                        username TEXT NOT NULL UNIQUE,
-                        password TEXT NOT NULL
-                    ) STRICT;
-                    CREATE INDEX IF NOT EXISTS username_index ON users (username);
-                    CREATE TABLE IF NOT EXISTS posts (
-                        postNum INTEGER PRIMARY KEY AUTOINCREMENT,
-                        username TEXT NOT NULL,
-                        message TEXT NOT NULL,
-                        time TEXT NOT NULL
-                    ) STRICT;
-                    CREATE TABLE IF NOT EXISTS sessions (
-                        username TEXT NOT NULL UNIQUE,
-                        sessionId TEXT NOT NULL UNIQUE,
-                        expiration INTEGER NOT NULL
-                    ) STRICT;
-                    CREATE INDEX IF NOT EXISTS sessions_index ON sessions (username, sessionId);
                 ",
             )
-            .unwrap();
+        });
+        let pool = Pool::new(manager).expect("Failed to open database file!");
+
+        let mut db = pool.get().expect("Failed to access database!");
+
+        migrations::run(&mut db).expect("Failed to migrate database!");
+
+        drop(db);
+
+        pool
+    }
+}
 
-        Self { pool }
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
     }
 }
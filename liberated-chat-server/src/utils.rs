@@ -2,31 +2,42 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
-use rusqlite::params;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use time::{format_description, OffsetDateTime};
+use rusqlite::{params, OptionalExtension};
+use std::{
+    env,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
-fn get_two_days() -> u64 {
-    let now = SystemTime::now();
-    let two_days = Duration::from_secs(2 * 24 * 60 * 60);
-    let two_days_from_now = now + two_days;
-    //Unwrap can never be reached, so long as system time is not before unix timestamp
-    two_days_from_now
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+//Stands in for the password of users that don't exist. Made with the same parameters as hash, so
+//checking against it takes just as long
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$BWtwIIbYNyKSXnl6EewT+w$Y9f8EbwUVZN87COM698vjRbktrPQfOiUiPBbGcRVET4";
+
+//Reads a number setting from the environment, falling back to default when it isn't set
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(v) => v
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} must be a number!")),
+        Err(_) => default,
+    }
 }
 
-fn get_time() -> u64 {
+//Every time in the database is one of these, a Unix timestamp in seconds
+pub fn get_time() -> u64 {
     let now = SystemTime::now();
     //Unwrap can never be reached, so long as system time is not before unix timestamp
     now.duration_since(UNIX_EPOCH).unwrap().as_secs()
 }
 
-pub fn get_formatted_time() -> String {
-    let now = OffsetDateTime::now_utc();
-    let format = format_description::parse("[month]/[day]/[year] @ [hour]:[minute]").unwrap();
-    now.format(&format).unwrap()
+//How times are sent to clients, e.g. 2024-01-31T13:45:00Z
+pub fn to_iso8601(time: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(time as i64)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+        .format(&Rfc3339)
+        .unwrap_or_default()
 }
 
 pub fn hash(password: &str) -> Result<String, argon2::password_hash::Error> {
@@ -49,27 +60,41 @@ pub fn register_user(
     password: &str,
     db: &rusqlite::Connection,
 ) -> Result<(), rusqlite::Error> {
-    let mut stmt = db.prepare_cached("INSERT INTO users VALUES (?, ?);")?;
+    let mut stmt =
+        db.prepare_cached("INSERT INTO users (username, usernameKey, password) VALUES (?, ?, ?);")?;
 
-    stmt.execute(params![username, password])?;
+    //Fails on a case-insensitive clash too, through the unique index on usernameKey
+    stmt.execute(params![
+        username,
+        super::policy::username_key(username),
+        password
+    ])?;
 
     Ok(())
 }
 
+//Starts a new session alongside any the user already has
 pub fn generate_session(
     username: &str,
+    user_agent: &str,
+    ip: &str,
+    config: &super::types::SessionConfig,
     db: &rusqlite::Connection,
 ) -> Result<String, rusqlite::Error> {
     let session = uuid::Uuid::new_v4().to_string();
+    let now = get_time();
+    let expiration = now + config.idle_timeout.min(config.max_age);
 
     db.execute(
-        "INSERT OR REPLACE INTO sessions (username, sessionId, expiration) VALUES (?, ?, ?);",
-        params![username, session, get_two_days()],
+        "INSERT INTO sessions (username, sessionId, expiration, createdAt, lastSeen, userAgent, ip)
+        VALUES (?, ?, ?, ?, ?, ?, ?);",
+        params![username, session, expiration, now, now, user_agent, ip],
     )?;
 
     Ok(session)
 }
 
+//False for a wrong password and for users that don't exist alike
 pub fn validate_password(
     username: &str,
     password: &str,
@@ -77,7 +102,17 @@ pub fn validate_password(
 ) -> Result<bool, Box<dyn std::error::Error>> {
     let mut stmt = db.prepare_cached("SELECT password FROM users WHERE username = ?;")?;
 
-    let password_hash: String = stmt.query_row(params![username], |row| row.get::<_, String>(0))?;
+    let password_hash: Option<String> = stmt
+        .query_row(params![username], |row| row.get::<_, String>(0))
+        .optional()?;
+
+    //Users that don't exist are checked against a stand-in, so they take as long as a wrong
+    //password would and the timing gives nothing away
+    let Some(password_hash) = password_hash else {
+        _ = verify_hash(password, DUMMY_HASH);
+
+        return Ok(false);
+    };
 
     let authorized = match verify_hash(password, &password_hash) {
         Ok(v) => v,
@@ -87,6 +122,7 @@ pub fn validate_password(
     Ok(authorized)
 }
 
+//Errors if the user has no sessions at all
 pub fn validate_session(
     username: &str,
     session: &str,
@@ -94,23 +130,121 @@ pub fn validate_session(
 ) -> Result<bool, rusqlite::Error> {
     let mut stmt =
         db.prepare_cached("SELECT sessionId, expiration FROM sessions WHERE username = ?;")?;
-    let session_info = stmt.query_row(params![username], |row| {
-        // Fetch sessionId and expiration from the row
-        let session_id: String = row.get(0)?;
-        let expiration: u64 = row.get(1)?;
+    let sessions = stmt
+        .query_map(params![username], |row| {
+            // Fetch sessionId and expiration from the row
+            let session_id: String = row.get(0)?;
+            let expiration: u64 = row.get(1)?;
 
-        // Return session_id and expiration as a tuple
-        Ok((session_id, expiration))
-    })?;
+            // Return session_id and expiration as a tuple
+            Ok((session_id, expiration))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if sessions.is_empty() {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+
+    Ok(sessions
+        .iter()
+        .any(|(session_id, expiration)| (session == session_id) && (get_time() < *expiration)))
+}
+
+//Pushes the expiry of a live session back by the idle timeout, never past its maximum age.
+//Returns how many seconds the session now has left, or None if it had already expired
+pub fn renew_session(
+    session: &str,
+    config: &super::types::SessionConfig,
+    db: &rusqlite::Connection,
+) -> Result<Option<u64>, rusqlite::Error> {
+    let now = get_time();
+
+    let mut stmt = db.prepare_cached(
+        "UPDATE sessions SET lastSeen = ?1, expiration = MIN(?1 + ?2, createdAt + ?3)
+        WHERE sessionId = ?4 AND expiration > ?1
+        RETURNING expiration - ?1;",
+    )?;
+
+    stmt.query_row(
+        params![now, config.idle_timeout, config.max_age, session],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+//Returns how many expired sessions were deleted
+pub fn purge_expired_sessions(db: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    db.execute(
+        "DELETE FROM sessions WHERE expiration <= ?;",
+        params![get_time()],
+    )
+}
 
-    // Unpack the session info tuple
-    let (session_id, expiration) = session_info;
+//Seconds until key (a username or IP, see 004_login_failures.sql) may try to log in again,
+//if it is locked out
+pub fn get_login_lockout(
+    key: &str,
+    db: &rusqlite::Connection,
+) -> Result<Option<u64>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT lockedUntil - ?1 FROM login_failures WHERE key = ?2 AND lockedUntil > ?1;",
+    )?;
+
+    stmt.query_row(params![get_time(), key], |row| row.get(0))
+        .optional()
+}
+
+//Counts a failed login against key and locks it out for a while, returning until when
+pub fn record_login_failure(
+    key: &str,
+    max_failures: u64,
+    config: &super::types::LoginLimitConfig,
+    db: &rusqlite::Connection,
+) -> Result<u64, rusqlite::Error> {
+    let now = get_time();
+
+    //Starts counting again once the last failure is old enough to be forgotten
+    let failures: u64 = db.query_row(
+        "INSERT INTO login_failures (key, failures, lastFailure, lockedUntil) VALUES (?1, 1, ?2, 0)
+            ON CONFLICT (key) DO UPDATE SET
+                failures = CASE WHEN lastFailure + ?3 < ?2 THEN 1 ELSE failures + 1 END,
+                lastFailure = ?2
+            RETURNING failures;",
+        params![key, now, config.lockout],
+        |row| row.get(0),
+    )?;
 
-    if (session == session_id) && (get_time() < expiration) {
-        Ok(true)
+    let delay = if failures >= max_failures {
+        config.lockout
     } else {
-        Ok(false)
-    }
+        (1u64 << (failures - 1).min(32)).min(config.backoff_max)
+    };
+
+    db.execute(
+        "UPDATE login_failures SET lockedUntil = ? WHERE key = ?;",
+        params![now + delay, key],
+    )?;
+
+    Ok(now + delay)
+}
+
+pub fn clear_login_failures(key: &str, db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    db.execute("DELETE FROM login_failures WHERE key = ?;", params![key])?;
+
+    Ok(())
+}
+
+//Drops failures that would be forgotten anyway
+pub fn purge_login_failures(
+    config: &super::types::LoginLimitConfig,
+    db: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    let now = get_time();
+
+    db.execute(
+        "DELETE FROM login_failures WHERE lastFailure + ? < ? AND lockedUntil <= ?;",
+        params![config.lockout, now, now],
+    )
 }
 
 pub fn get_username_from_session(
@@ -121,131 +255,1863 @@ pub fn get_username_from_session(
     stmt.query_row(params![session], |row| row.get::<_, String>(0))
 }
 
-pub fn get_posts(db: &rusqlite::Connection) -> Result<Vec<super::types::Post>, rusqlite::Error> {
-    let mut stmt = db.prepare_cached("SELECT * FROM posts;")?;
+pub fn room_exists(room: u64, db: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+    let mut stmt = db.prepare_cached("SELECT 1 FROM rooms WHERE roomId = ?;")?;
+    stmt.exists(params![room])
+}
+
+//Everyone is in the general room, every other room has to be joined first
+pub fn can_access_room(
+    username: &str,
+    room: u64,
+    db: &rusqlite::Connection,
+) -> Result<bool, rusqlite::Error> {
+    if room == super::types::GENERAL_ROOM {
+        return Ok(true);
+    }
+
+    let mut stmt =
+        db.prepare_cached("SELECT 1 FROM room_members WHERE roomId = ? AND username = ?;")?;
+    stmt.exists(params![room, username])
+}
+
+pub fn get_rooms(
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Room>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT rooms.roomId, rooms.name, rooms.roomId = ? OR room_members.username IS NOT NULL
+        FROM rooms
+        LEFT JOIN room_members
+            ON room_members.roomId = rooms.roomId AND room_members.username = ?
+        ORDER BY rooms.roomId;",
+    )?;
 
-    let posts_iter = stmt.query_map(params![], |row| {
-        Ok(super::types::Post {
-            post_num: row.get(0)?,
-            user: row.get(1)?,
-            message: row.get(2)?,
-            time: row.get(3)?,
+    let rooms_iter = stmt.query_map(params![super::types::GENERAL_ROOM, username], |row| {
+        Ok(super::types::Room {
+            room_id: row.get(0)?,
+            name: row.get(1)?,
+            joined: row.get(2)?,
         })
     })?;
 
-    let posts: Vec<super::types::Post> = match posts_iter.collect() {
-        Ok(v) => v,
-        Err(e) => return Err(e),
-    };
+    rooms_iter.collect()
+}
+
+pub fn create_room(
+    name: &str,
+    creator: &str,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Room, rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+
+    tx.execute(
+        "INSERT INTO rooms (name, creator) VALUES (?, ?);",
+        params![name, creator],
+    )?;
+
+    let room_id = tx.last_insert_rowid() as u64;
 
-    Ok(posts)
+    tx.execute(
+        "INSERT INTO room_members (roomId, username) VALUES (?, ?);",
+        params![room_id, creator],
+    )?;
+
+    tx.commit()?;
+
+    Ok(super::types::Room {
+        room_id,
+        name: name.into(),
+        joined: true,
+    })
 }
 
-pub fn send_message(
-    message: &super::types::InsertPost,
+pub fn join_room(
+    room: u64,
+    username: &str,
     db: &rusqlite::Connection,
 ) -> Result<(), rusqlite::Error> {
     db.execute(
-        "INSERT INTO posts (username, message, time) VALUES (?, ?, ?);",
-        params![message.user, message.message, message.time],
+        "INSERT OR IGNORE INTO room_members (roomId, username) VALUES (?, ?);",
+        params![room, username],
     )?;
 
     Ok(())
 }
 
-pub fn logout(username: &str, db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
-    db.execute(
-        "DELETE FROM sessions WHERE username = ?;",
-        params![username],
+fn row_to_post(row: &rusqlite::Row) -> Result<super::types::Post, rusqlite::Error> {
+    Ok(super::types::Post {
+        post_num: row.get(0)?,
+        room: row.get(1)?,
+        user: row.get(2)?,
+        message: row.get(3)?,
+        time: to_iso8601(row.get(4)?),
+        edited_at: row.get::<_, Option<u64>>(5)?.map(to_iso8601),
+        deleted: row.get(6)?,
+        attachments: Vec::new(),
+        reactions: Vec::new(),
+        parent_post_num: row.get(7)?,
+        reply_count: row.get(8)?,
+    })
+}
+
+pub fn get_attachments(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Attachment>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT attachmentNum, name, mime, size FROM attachments
+        JOIN files USING (hash)
+        WHERE postNum = ?
+        ORDER BY attachmentNum;",
     )?;
 
-    Ok(())
+    let attachments_iter = stmt.query_map(params![post_num], |row| {
+        Ok(super::types::Attachment {
+            attachment_num: row.get(0)?,
+            name: row.get(1)?,
+            mime: row.get(2)?,
+            size: row.get(3)?,
+        })
+    })?;
+
+    attachments_iter.collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub fn get_reactions(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Reaction>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT emoji, username FROM reactions WHERE postNum = ? ORDER BY time, rowid;",
+    )?;
 
-    #[test]
-    fn test_validate_password_hash() {
-        let password = "Hello, world!";
+    let mut reactions: Vec<super::types::Reaction> = Vec::new();
 
-        //Creates test db
-        let db = rusqlite::Connection::open_in_memory().unwrap();
+    let mut rows = stmt.query(params![post_num])?;
 
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS users (
-                        username TEXT NOT NULL UNIQUE,
-                        password BLOB NOT NULL
-                    );",
-            rusqlite::params![],
-        )
-        .unwrap();
+    while let Some(row) = rows.next()? {
+        let emoji: String = row.get(0)?;
+        let username: String = row.get(1)?;
 
-        let hash = hash(password).unwrap();
+        match reactions
+            .iter_mut()
+            .find(|reaction| reaction.emoji == emoji)
+        {
+            Some(reaction) => {
+                reaction.count += 1;
+                reaction.users.push(username);
+            }
+            None => reactions.push(super::types::Reaction {
+                emoji,
+                count: 1,
+                users: vec![username],
+            }),
+        }
+    }
 
-        db.execute(
-            "INSERT INTO users VALUES ('john', ?)",
-            rusqlite::params![hash],
-        )
-        .unwrap();
+    Ok(reactions)
+}
 
-        let valid = validate_password("john", password, &db).unwrap();
+//Fills in the attachments and reactions of posts fresh out of row_to_post
+fn with_details(
+    posts: Result<Vec<super::types::Post>, rusqlite::Error>,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Post>, rusqlite::Error> {
+    posts?
+        .into_iter()
+        .map(|mut post| {
+            post.attachments = get_attachments(post.post_num, db)?;
+            post.reactions = get_reactions(post.post_num, db)?;
+            Ok(post)
+        })
+        .collect()
+}
 
-        //Ensure hash is valid
-        assert!(valid);
+//Returns the newest `limit` posts in a room older than `before` (or the newest overall),
+//oldest first. Replies are left out, they are only seen in their thread
+pub fn get_posts(
+    room: u64,
+    before: Option<u64>,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Post>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT * FROM (
+            SELECT postNum, roomId, username, message, time, editedAt, deleted, parentPostNum,
+                (SELECT count(*) FROM posts AS replies
+                    WHERE replies.parentPostNum = posts.postNum AND replies.deleted = 0)
+            FROM posts
+            WHERE roomId = ? AND postNum < ? AND parentPostNum IS NULL
+            ORDER BY postNum DESC LIMIT ?
+        ) ORDER BY postNum;",
+    )?;
 
-        db.close().unwrap();
-    }
+    let posts_iter = stmt.query_map(
+        params![room, before.unwrap_or(i64::MAX as u64), limit],
+        row_to_post,
+    )?;
 
-    #[test]
-    fn test_new_user() {
-        //Creates test database
-        let db = rusqlite::Connection::open_in_memory().unwrap();
+    with_details(posts_iter.collect(), db)
+}
 
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS users (
-                        username TEXT NOT NULL UNIQUE,
-                        password BLOB NOT NULL
-                    );",
-            rusqlite::params![],
+//Returns posts in a room newer than `post_num`, oldest first. Replies are left out here too
+pub fn get_posts_after(
+    room: u64,
+    post_num: u64,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Post>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT postNum, roomId, username, message, time, editedAt, deleted, parentPostNum,
+            (SELECT count(*) FROM posts AS replies
+                WHERE replies.parentPostNum = posts.postNum AND replies.deleted = 0)
+        FROM posts
+        WHERE roomId = ? AND postNum > ? AND parentPostNum IS NULL
+        ORDER BY postNum LIMIT ?;",
+    )?;
+
+    let posts_iter = stmt.query_map(params![room, post_num, limit], row_to_post)?;
+
+    with_details(posts_iter.collect(), db)
+}
+
+//Number of the newest post in any room, 0 if there are none
+pub fn get_last_post_num(db: &rusqlite::Connection) -> Result<u64, rusqlite::Error> {
+    db.query_row(
+        "SELECT coalesce(max(postNum), 0) FROM posts;",
+        params![],
+        |row| row.get(0),
+    )
+}
+
+//Returns up to `limit` posts newer than `post_num` in any room the user can see, oldest first,
+//replies included
+pub fn get_visible_posts_after(
+    username: &str,
+    post_num: u64,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Post>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT postNum, roomId, username, message, time, editedAt, deleted, parentPostNum,
+            (SELECT count(*) FROM posts AS replies
+                WHERE replies.parentPostNum = posts.postNum AND replies.deleted = 0)
+        FROM posts
+        WHERE postNum > ? AND (
+            roomId = ? OR roomId IN (SELECT roomId FROM room_members WHERE username = ?)
         )
-        .unwrap();
+        ORDER BY postNum
+        LIMIT ?;",
+    )?;
 
-        //Should not return error
-        assert!(super::register_user("jack", "password", &db).ok().is_some());
+    let posts_iter = stmt.query_map(
+        params![post_num, super::types::GENERAL_ROOM, username, limit],
+        row_to_post,
+    )?;
 
-        //Should return error
-        assert!(super::register_user("jack", "password", &db)
-            .err()
-            .is_some());
+    with_details(posts_iter.collect(), db)
+}
 
-        db.close().unwrap();
+pub fn send_message(
+    message: &super::types::InsertPost,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Post, rusqlite::Error> {
+    db.execute(
+        "INSERT INTO posts (roomId, username, message, time, parentPostNum) VALUES (?, ?, ?, ?, ?);",
+        params![
+            message.room,
+            message.user,
+            message.message,
+            message.time,
+            message.parent_post_num
+        ],
+    )?;
+
+    Ok(super::types::Post {
+        post_num: db.last_insert_rowid() as u64,
+        room: message.room,
+        user: message.user.clone(),
+        message: message.message.clone(),
+        time: to_iso8601(message.time),
+        edited_at: None,
+        deleted: false,
+        attachments: Vec::new(),
+        reactions: Vec::new(),
+        parent_post_num: message.parent_post_num,
+        reply_count: 0,
+    })
+}
+
+//The files themselves have to be on disk already, see attachment::AttachmentStore::send
+pub fn send_message_with_attachments(
+    message: &super::types::InsertPost,
+    files: &[super::attachment::UploadedFile],
+    db: &rusqlite::Connection,
+) -> Result<super::types::Post, rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+
+    let post = send_message(message, &tx)?;
+
+    for file in files {
+        //Already there if someone uploaded the same file before
+        tx.execute(
+            "INSERT OR IGNORE INTO files (hash, size) VALUES (?, ?);",
+            params![file.hash, file.data.len()],
+        )?;
+        tx.execute(
+            "INSERT INTO attachments (postNum, hash, name, mime) VALUES (?, ?, ?, ?);",
+            params![post.post_num, file.hash, file.name, file.mime],
+        )?;
     }
 
-    #[test]
-    fn test_validate_session() {
-        // Create a test session for validation
-        let test_session_id = uuid::Uuid::new_v4().to_string();
-        let test_username = "test_user";
-        let test_expiration = get_two_days();
+    tx.commit()?;
 
-        let db = rusqlite::Connection::open_in_memory().unwrap();
+    get_post(post.post_num, db)
+}
 
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                        username TEXT NOT NULL,
-                        sessionId TEXT NOT NULL,
-                        expiration INTEGER NOT NULL
-                    );",
-            rusqlite::params![],
+pub fn get_post(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Post, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT postNum, roomId, username, message, time, editedAt, deleted, parentPostNum,
+            (SELECT count(*) FROM posts AS replies
+                WHERE replies.parentPostNum = posts.postNum AND replies.deleted = 0)
+        FROM posts
+        WHERE postNum = ?;",
+    )?;
+
+    let mut post = stmt.query_row(params![post_num], row_to_post)?;
+    post.attachments = get_attachments(post_num, db)?;
+    post.reactions = get_reactions(post_num, db)?;
+
+    Ok(post)
+}
+
+//Every reply to the post, oldest first
+pub fn get_replies(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Post>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT postNum, roomId, username, message, time, editedAt, deleted, parentPostNum, 0
+        FROM posts
+        WHERE parentPostNum = ?
+        ORDER BY postNum;",
+    )?;
+
+    let posts_iter = stmt.query_map(params![post_num], row_to_post)?;
+
+    with_details(posts_iter.collect(), db)
+}
+
+//The thread a post belongs to, starting from the post itself or any reply to it
+pub fn get_thread(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Thread, rusqlite::Error> {
+    let post = get_post(post_num, db)?;
+
+    let post = match post.parent_post_num {
+        Some(parent_post_num) => get_post(parent_post_num, db)?,
+        None => post,
+    };
+
+    Ok(super::types::Thread {
+        replies: get_replies(post.post_num, db)?,
+        post,
+    })
+}
+
+pub fn edit_post(
+    post_num: u64,
+    message: &str,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Post, rusqlite::Error> {
+    db.execute(
+        "UPDATE posts SET message = ?, editedAt = ? WHERE postNum = ? AND deleted = 0;",
+        params![message, get_time(), post_num],
+    )?;
+
+    get_post(post_num, db)
+}
+
+//Posts are never really removed, just emptied out and left as a tombstone.
+//Their attachments, reactions and mentions do go, and the files with them once nothing else uses
+//them
+pub fn delete_post(
+    post_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Post, rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+
+    tx.execute(
+        "UPDATE posts SET message = '', deleted = 1 WHERE postNum = ?;",
+        params![post_num],
+    )?;
+    tx.execute(
+        "DELETE FROM attachments WHERE postNum = ?;",
+        params![post_num],
+    )?;
+    tx.execute(
+        "DELETE FROM reactions WHERE postNum = ?;",
+        params![post_num],
+    )?;
+    tx.execute("DELETE FROM mentions WHERE postNum = ?;", params![post_num])?;
+
+    tx.commit()?;
+
+    get_post(post_num, db)
+}
+
+//Returns false if the user had already reacted with this emoji
+pub fn add_reaction(
+    post_num: u64,
+    username: &str,
+    emoji: &str,
+    db: &rusqlite::Connection,
+) -> Result<bool, rusqlite::Error> {
+    let added = db.execute(
+        "INSERT OR IGNORE INTO reactions (postNum, username, emoji, time) VALUES (?, ?, ?, ?);",
+        params![post_num, username, emoji, get_time()],
+    )?;
+
+    Ok(added > 0)
+}
+
+pub fn remove_reaction(
+    post_num: u64,
+    username: &str,
+    emoji: &str,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "DELETE FROM reactions WHERE postNum = ? AND username = ? AND emoji = ?;",
+        params![post_num, username, emoji],
+    )?;
+
+    Ok(())
+}
+
+//How many different emoji the user has reacted to the post with
+pub fn count_reactions(
+    post_num: u64,
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    db.query_row(
+        "SELECT count(*) FROM reactions WHERE postNum = ? AND username = ?;",
+        params![post_num, username],
+        |row| row.get(0),
+    )
+}
+
+//The attachment, the room of the post it is on and the hash of its file
+pub fn get_attachment(
+    attachment_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<Option<(super::types::Attachment, u64, String)>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT attachmentNum, name, mime, size, roomId, hash FROM attachments
+        JOIN files USING (hash)
+        JOIN posts USING (postNum)
+        WHERE attachmentNum = ?;",
+    )?;
+
+    stmt.query_row(params![attachment_num], |row| {
+        Ok((
+            super::types::Attachment {
+                attachment_num: row.get(0)?,
+                name: row.get(1)?,
+                mime: row.get(2)?,
+                size: row.get(3)?,
+            },
+            row.get(4)?,
+            row.get(5)?,
+        ))
+    })
+    .optional()
+}
+
+//Forgets files no attachment uses anymore, returning their hashes so they can be removed from disk
+pub fn purge_unused_files(db: &rusqlite::Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "DELETE FROM files WHERE hash NOT IN (SELECT hash FROM attachments) RETURNING hash;",
+    )?;
+
+    let hashes_iter = stmt.query_map(params![], |row| row.get(0))?;
+
+    hashes_iter.collect()
+}
+
+//The username going by this key, see policy::username_key
+pub fn find_username(
+    key: &str,
+    db: &rusqlite::Connection,
+) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached("SELECT username FROM users WHERE usernameKey = ?;")?;
+    stmt.query_row(params![key], |row| row.get(0)).optional()
+}
+
+pub fn add_mention(
+    post_num: u64,
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "INSERT OR IGNORE INTO mentions (postNum, username, time) VALUES (?, ?, ?);",
+        params![post_num, username, get_time()],
+    )?;
+
+    Ok(())
+}
+
+//The user's newest `limit` notifications, and how many are unread. Mentions in rooms the user
+//can't see anymore are left out
+pub fn get_notifications(
+    username: &str,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<super::types::Notifications, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT mentionNum, postNum, readAt IS NOT NULL FROM mentions
+        JOIN posts USING (postNum)
+        WHERE mentions.username = ?1 AND (
+            posts.roomId = ?2 OR posts.roomId IN (SELECT roomId FROM room_members WHERE username = ?1)
         )
-        .unwrap();
+        ORDER BY mentionNum DESC LIMIT ?3;",
+    )?;
+
+    let rows = stmt
+        .query_map(
+            params![username, super::types::GENERAL_ROOM, limit],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?
+        .collect::<Result<Vec<(u64, u64, bool)>, _>>()?;
+
+    let notifications = rows
+        .into_iter()
+        .map(|(mention_num, post_num, read)| {
+            Ok(super::types::Notification {
+                mention_num,
+                read,
+                post: get_post(post_num, db)?,
+            })
+        })
+        .collect::<Result<_, rusqlite::Error>>()?;
+
+    let unread = db.query_row(
+        "SELECT count(*) FROM mentions
+        JOIN posts USING (postNum)
+        WHERE mentions.username = ?1 AND readAt IS NULL AND (
+            posts.roomId = ?2 OR posts.roomId IN (SELECT roomId FROM room_members WHERE username = ?1)
+        );",
+        params![username, super::types::GENERAL_ROOM],
+        |row| row.get(0),
+    )?;
+
+    Ok(super::types::Notifications {
+        unread,
+        notifications,
+    })
+}
+
+//Marks one of the user's notifications read, or every one of them if `mention_num` is None
+pub fn mark_notifications_read(
+    username: &str,
+    mention_num: Option<u64>,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "UPDATE mentions SET readAt = ?1
+        WHERE username = ?2 AND readAt IS NULL AND (?3 IS NULL OR mentionNum = ?3);",
+        params![get_time(), username, mention_num],
+    )?;
+
+    Ok(())
+}
+
+//Posts matching an FTS5 query (see search::to_match_query) in rooms the user can see, best
+//matches first. `author` and the dates narrow it down further, see types::SearchQuery
+pub fn search_posts(
+    username: &str,
+    terms: &str,
+    author: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::SearchResult>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT posts.postNum, snippet(posts_search, 0, ?1, ?2, '...', 16) FROM posts_search
+        JOIN posts ON posts.postNum = posts_search.rowid
+        WHERE posts_search MATCH ?3 AND posts.deleted = 0
+            AND (posts.roomId = ?4 OR posts.roomId IN (
+                SELECT roomId FROM room_members WHERE username = ?5
+            ))
+            AND (?6 IS NULL OR posts.username = ?6)
+            AND (?7 IS NULL OR posts.time >= unixepoch(?7))
+            AND (?8 IS NULL OR posts.time < unixepoch(?8, '+1 day'))
+        ORDER BY rank LIMIT ?9;",
+    )?;
+
+    let rows = stmt
+        .query_map(
+            params![
+                super::search::HIGHLIGHT_START.to_string(),
+                super::search::HIGHLIGHT_END.to_string(),
+                terms,
+                super::types::GENERAL_ROOM,
+                username,
+                author,
+                from,
+                to,
+                limit
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .collect::<Result<Vec<(u64, String)>, _>>()?;
+
+    rows.into_iter()
+        .map(|(post_num, snippet)| {
+            Ok(super::types::SearchResult {
+                post: get_post(post_num, db)?,
+                snippet: super::search::split_snippet(&snippet),
+            })
+        })
+        .collect()
+}
+
+pub fn user_exists(username: &str, db: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+    let mut stmt = db.prepare_cached("SELECT 1 FROM users WHERE username = ?;")?;
+    stmt.exists(params![username])
+}
+
+fn row_to_direct_message(
+    row: &rusqlite::Row,
+) -> Result<super::types::DirectMessage, rusqlite::Error> {
+    Ok(super::types::DirectMessage {
+        dm_num: row.get(0)?,
+        from: row.get(1)?,
+        to: row.get(2)?,
+        message: row.get(3)?,
+        time: to_iso8601(row.get(4)?),
+    })
+}
+
+pub fn send_direct_message(
+    message: &super::types::InsertDirectMessage,
+    db: &rusqlite::Connection,
+) -> Result<super::types::DirectMessage, rusqlite::Error> {
+    db.execute(
+        "INSERT INTO direct_messages (sender, recipient, message, time) VALUES (?, ?, ?, ?);",
+        params![message.from, message.to, message.message, message.time],
+    )?;
+
+    Ok(super::types::DirectMessage {
+        dm_num: db.last_insert_rowid() as u64,
+        from: message.from.clone(),
+        to: message.to.clone(),
+        message: message.message.clone(),
+        time: to_iso8601(message.time),
+    })
+}
+
+//Returns the newest `limit` messages between two users older than `before`, oldest first
+pub fn get_direct_messages(
+    user: &str,
+    other: &str,
+    before: Option<u64>,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::DirectMessage>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT * FROM (
+            SELECT dmNum, sender, recipient, message, time FROM direct_messages
+            WHERE ((sender = ?1 AND recipient = ?2) OR (sender = ?2 AND recipient = ?1))
+                AND dmNum < ?3
+            ORDER BY dmNum DESC LIMIT ?4
+        ) ORDER BY dmNum;",
+    )?;
+
+    let messages_iter = stmt.query_map(
+        params![user, other, before.unwrap_or(i64::MAX as u64), limit],
+        row_to_direct_message,
+    )?;
+
+    messages_iter.collect()
+}
+
+//Returns messages between two users newer than `dm_num`, oldest first
+pub fn get_direct_messages_after(
+    user: &str,
+    other: &str,
+    dm_num: u64,
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::DirectMessage>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT dmNum, sender, recipient, message, time FROM direct_messages
+        WHERE ((sender = ?1 AND recipient = ?2) OR (sender = ?2 AND recipient = ?1))
+            AND dmNum > ?3
+        ORDER BY dmNum LIMIT ?4;",
+    )?;
+
+    let messages_iter =
+        stmt.query_map(params![user, other, dm_num, limit], row_to_direct_message)?;
+
+    messages_iter.collect()
+}
+
+//Returns everyone the user has talked to, most recently active first
+pub fn get_conversations(
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::Conversation>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT dmNum, sender, recipient, message, time FROM direct_messages
+        WHERE dmNum IN (
+            SELECT MAX(dmNum) FROM direct_messages
+            WHERE sender = ?1 OR recipient = ?1
+            GROUP BY CASE WHEN sender = ?1 THEN recipient ELSE sender END
+        )
+        ORDER BY dmNum DESC;",
+    )?;
+
+    let conversations_iter = stmt.query_map(params![username], |row| {
+        let last_message = row_to_direct_message(row)?;
+
+        let user = if last_message.from == username {
+            last_message.to.clone()
+        } else {
+            last_message.from.clone()
+        };
+
+        Ok(super::types::Conversation { user, last_message })
+    })?;
+
+    conversations_iter.collect()
+}
+
+//Returns the user's role and whether they are banned
+pub fn get_role(
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<(super::types::Role, bool), rusqlite::Error> {
+    let mut stmt = db.prepare_cached("SELECT role, banned FROM users WHERE username = ?;")?;
+
+    stmt.query_row(params![username], |row| {
+        let role: String = row.get(0)?;
+        let banned: bool = row.get(1)?;
+
+        //Anything unrecognised gets the least trust
+        Ok((role.parse().unwrap_or(super::types::Role::User), banned))
+    })
+}
+
+pub fn set_role(
+    username: &str,
+    role: super::types::Role,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "UPDATE users SET role = ? WHERE username = ?;",
+        params![role.as_str(), username],
+    )?;
+
+    Ok(())
+}
+
+//Banning also ends every session the user has open
+pub fn set_banned(
+    username: &str,
+    banned: bool,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "UPDATE users SET banned = ? WHERE username = ?;",
+        params![banned, username],
+    )?;
+
+    if banned {
+        logout(username, db)?;
+    }
+
+    Ok(())
+}
+
+pub fn log_moderation(
+    moderator: &str,
+    action: &str,
+    target: &str,
+    reason: &str,
+    db: &rusqlite::Connection,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "INSERT INTO moderation_log (moderator, action, target, reason, time)
+        VALUES (?, ?, ?, ?, ?);",
+        params![moderator, action, target, reason, get_time()],
+    )?;
+
+    Ok(())
+}
+
+//Returns the newest `limit` moderation actions, newest first
+pub fn get_moderation_log(
+    limit: u64,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::ModerationAction>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT actionNum, moderator, action, target, reason, time FROM moderation_log
+        ORDER BY actionNum DESC LIMIT ?;",
+    )?;
+
+    let actions_iter = stmt.query_map(params![limit], |row| {
+        Ok(super::types::ModerationAction {
+            action_num: row.get(0)?,
+            moderator: row.get(1)?,
+            action: row.get(2)?,
+            target: row.get(3)?,
+            reason: row.get(4)?,
+            time: to_iso8601(row.get(5)?),
+        })
+    })?;
+
+    actions_iter.collect()
+}
+
+pub fn list_users(
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::UserInfo>, rusqlite::Error> {
+    let mut stmt =
+        db.prepare_cached("SELECT username, role, banned FROM users ORDER BY username;")?;
+
+    let users_iter = stmt.query_map(params![], |row| {
+        let role: String = row.get(1)?;
+
+        Ok(super::types::UserInfo {
+            username: row.get(0)?,
+            role: role.parse().unwrap_or(super::types::Role::User),
+            banned: row.get(2)?,
+        })
+    })?;
+
+    users_iter.collect()
+}
+
+//Removes the account, its sessions, room memberships and notifications. Posts and direct messages
+//are kept.
+//Returns false if there was no such user
+pub fn delete_user(username: &str, db: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+
+    tx.execute(
+        "DELETE FROM sessions WHERE username = ?;",
+        params![username],
+    )?;
+    tx.execute(
+        "DELETE FROM room_members WHERE username = ?;",
+        params![username],
+    )?;
+    tx.execute(
+        "DELETE FROM mentions WHERE username = ?;",
+        params![username],
+    )?;
+    let deleted = tx.execute("DELETE FROM users WHERE username = ?;", params![username])?;
+
+    tx.commit()?;
+
+    Ok(deleted > 0)
+}
+
+//Renames the account everywhere it is referenced, except the moderation log which stays as it was
+pub fn rename_user(
+    username: &str,
+    new_username: &str,
+    db: &rusqlite::Connection,
+) -> Result<bool, rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+
+    let renamed = tx.execute(
+        "UPDATE users SET username = ?, usernameKey = ? WHERE username = ?;",
+        params![
+            new_username,
+            super::policy::username_key(new_username),
+            username
+        ],
+    )?;
+
+    if renamed == 0 {
+        return Ok(false);
+    }
+
+    tx.execute(
+        "UPDATE sessions SET username = ? WHERE username = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE posts SET username = ? WHERE username = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE reactions SET username = ? WHERE username = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE mentions SET username = ? WHERE username = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE room_members SET username = ? WHERE username = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE rooms SET creator = ? WHERE creator = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE direct_messages SET sender = ? WHERE sender = ?;",
+        params![new_username, username],
+    )?;
+    tx.execute(
+        "UPDATE direct_messages SET recipient = ? WHERE recipient = ?;",
+        params![new_username, username],
+    )?;
+
+    tx.commit()?;
+
+    Ok(true)
+}
+
+//Takes an already hashed password, and logs the user out everywhere.
+//Returns false if there was no such user
+pub fn set_password(
+    username: &str,
+    password: &str,
+    db: &rusqlite::Connection,
+) -> Result<bool, rusqlite::Error> {
+    let updated = db.execute(
+        "UPDATE users SET password = ? WHERE username = ?;",
+        params![password, username],
+    )?;
+
+    logout(username, db)?;
+
+    Ok(updated > 0)
+}
+
+//Lists every session, or only the given user's. `current` marks the session asking, if any
+pub fn list_sessions(
+    username: Option<&str>,
+    current: Option<&str>,
+    db: &rusqlite::Connection,
+) -> Result<Vec<super::types::SessionInfo>, rusqlite::Error> {
+    let mut stmt = db.prepare_cached(
+        "SELECT sessionNum, username, createdAt, lastSeen, expiration, userAgent, ip,
+        sessionId IS ?2
+        FROM sessions
+        WHERE ?1 IS NULL OR username = ?1
+        ORDER BY username, lastSeen DESC;",
+    )?;
+
+    let sessions_iter = stmt.query_map(params![username, current], |row| {
+        Ok(super::types::SessionInfo {
+            session_num: row.get(0)?,
+            username: row.get(1)?,
+            created_at: row.get(2)?,
+            last_seen: row.get(3)?,
+            expiration: row.get(4)?,
+            user_agent: row.get(5)?,
+            ip: row.get(6)?,
+            current: row.get(7)?,
+        })
+    })?;
+
+    sessions_iter.collect()
+}
+
+//Only revokes sessions belonging to `username`. Returns false if there was no such session
+pub fn revoke_session(
+    username: &str,
+    session_num: u64,
+    db: &rusqlite::Connection,
+) -> Result<bool, rusqlite::Error> {
+    let revoked = db.execute(
+        "DELETE FROM sessions WHERE sessionNum = ? AND username = ?;",
+        params![session_num, username],
+    )?;
+
+    Ok(revoked > 0)
+}
+
+//Logs the user out everywhere but `current`. Returns how many sessions were ended
+pub fn revoke_other_sessions(
+    username: &str,
+    current: &str,
+    db: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    db.execute(
+        "DELETE FROM sessions WHERE username = ? AND sessionId != ?;",
+        params![username, current],
+    )
+}
+
+//The three functions below remove posts outright, unlike delete_post. They return how many went
+pub fn purge_post(post_num: u64, db: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    db.execute("DELETE FROM posts WHERE postNum = ?;", params![post_num])
+}
+
+pub fn purge_posts_by_user(
+    username: &str,
+    db: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    db.execute("DELETE FROM posts WHERE username = ?;", params![username])
+}
+
+//Both dates are YYYY-MM-DD and inclusive
+pub fn purge_posts_between(
+    from: &str,
+    to: &str,
+    db: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    //Dates are midnight UTC, so the end of the range is the day after `to`
+    db.execute(
+        "DELETE FROM posts WHERE time >= unixepoch(?) AND time < unixepoch(?, '+1 day');",
+        params![from, to],
+    )
+}
+
+pub fn get_stats(
+    db: &rusqlite::Connection,
+) -> Result<super::types::DatabaseStats, rusqlite::Error> {
+    let count = |sql: &str| db.query_row(sql, params![], |row| row.get::<_, u64>(0));
+
+    Ok(super::types::DatabaseStats {
+        users: count("SELECT COUNT(*) FROM users;")?,
+        banned_users: count("SELECT COUNT(*) FROM users WHERE banned = 1;")?,
+        sessions: count("SELECT COUNT(*) FROM sessions;")?,
+        rooms: count("SELECT COUNT(*) FROM rooms;")?,
+        posts: count("SELECT COUNT(*) FROM posts;")?,
+        deleted_posts: count("SELECT COUNT(*) FROM posts WHERE deleted = 1;")?,
+        direct_messages: count("SELECT COUNT(*) FROM direct_messages;")?,
+        size_bytes: count(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size();",
+        )?,
+    })
+}
+
+//Ends just the one session
+pub fn end_session(session: &str, db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "DELETE FROM sessions WHERE sessionId = ?;",
+        params![session],
+    )?;
+
+    Ok(())
+}
+
+//Ends every session the user has
+pub fn logout(username: &str, db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "DELETE FROM sessions WHERE username = ?;",
+        params![username],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: super::super::types::SessionConfig = super::super::types::SessionConfig {
+        idle_timeout: 60,
+        max_age: 90,
+        purge_interval: 60,
+    };
+
+    //A fresh database with the real schema, opened the way the server opens one
+    fn test_db() -> rusqlite::Connection {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+
+        db.execute_batch("PRAGMA foreign_keys = true;").unwrap();
+        super::super::migrations::run(&mut db).unwrap();
+
+        db
+    }
+
+    #[test]
+    fn test_validate_password_hash() {
+        let password = "Hello, world!";
+
+        //Creates test db
+        let db = test_db();
+
+        let hash = hash(password).unwrap();
+
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('john', ?)",
+            rusqlite::params![hash],
+        )
+        .unwrap();
+
+        let valid = validate_password("john", password, &db).unwrap();
+
+        //Ensure hash is valid
+        assert!(valid);
+
+        //Users that don't exist are still checked, against a hash that matches nothing
+        assert!(!validate_password("jack", password, &db).unwrap());
+        assert!(PasswordHash::new(DUMMY_HASH).is_ok());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_new_user() {
+        //Creates test database
+        let db = test_db();
+
+        //Should not return error
+        assert!(super::register_user("jack", "password", &db).ok().is_some());
+
+        //Should return error
+        assert!(super::register_user("jack", "password", &db)
+            .err()
+            .is_some());
+
+        //Differs only in case, so is taken too
+        assert!(super::register_user("Jack", "password", &db).is_err());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_send_message() {
+        let db = test_db();
+
+        let post = super::super::types::InsertPost {
+            room: 1,
+            user: "john".into(),
+            message: "Hello, world!".into(),
+            time: get_time(),
+            parent_post_num: None,
+        };
+
+        let first = send_message(&post, &db).unwrap();
+        let second = send_message(&post, &db).unwrap();
+
+        //Returned posts should carry the number the database assigned them
+        assert_eq!(first.post_num, 1);
+        assert_eq!(second.post_num, 2);
+        assert_eq!(
+            get_posts(1, None, 10, &db).unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+        assert_eq!(
+            get_posts_after(1, first.post_num, 10, &db).unwrap(),
+            vec![second]
+        );
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_get_posts_pages() {
+        let db = test_db();
+
+        for i in 0..10 {
+            let post = super::super::types::InsertPost {
+                room: 1,
+                user: "john".into(),
+                message: format!("Message {i}"),
+                time: get_time(),
+                parent_post_num: None,
+            };
+
+            send_message(&post, &db).unwrap();
+        }
+
+        let nums = |posts: Vec<super::super::types::Post>| -> Vec<u64> {
+            posts.iter().map(|post| post.post_num).collect()
+        };
+
+        //Newest page, still in chronological order
+        assert_eq!(nums(get_posts(1, None, 3, &db).unwrap()), vec![8, 9, 10]);
+
+        //Page of history right before the oldest post we have
+        assert_eq!(nums(get_posts(1, Some(8), 3, &db).unwrap()), vec![5, 6, 7]);
+
+        //Runs out at the start of history
+        assert_eq!(nums(get_posts(1, Some(3), 3, &db).unwrap()), vec![1, 2]);
+
+        //Catching up from a known post
+        assert_eq!(nums(get_posts_after(1, 7, 2, &db).unwrap()), vec![8, 9]);
+        assert_eq!(
+            nums(get_posts_after(1, 7, 10, &db).unwrap()),
+            vec![8, 9, 10]
+        );
+
+        //Other rooms have none of these posts
+        assert!(get_posts(2, None, 3, &db).unwrap().is_empty());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_edit_and_delete_post() {
+        let db = test_db();
+
+        let post = super::super::types::InsertPost {
+            room: 1,
+            user: "john".into(),
+            message: "Hello, wrold!".into(),
+            time: get_time(),
+            parent_post_num: None,
+        };
+
+        let post = send_message(&post, &db).unwrap();
+        assert!(post.edited_at.is_none());
+
+        let edited = edit_post(post.post_num, "Hello, world!", &db).unwrap();
+        assert_eq!(edited.message, "Hello, world!");
+        assert!(edited.edited_at.is_some());
+
+        let deleted = delete_post(post.post_num, &db).unwrap();
+        assert!(deleted.deleted);
+        assert!(deleted.message.is_empty());
+
+        //Tombstones stay in the history but can't be brought back by editing
+        let edited = edit_post(post.post_num, "I'm back", &db).unwrap();
+        assert!(edited.message.is_empty());
+        assert_eq!(get_posts(1, None, 10, &db).unwrap(), vec![edited]);
+
+        //Missing posts are an error
+        assert!(get_post(100, &db).is_err());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_reactions() {
+        let db = test_db();
+
+        let post = super::super::types::InsertPost {
+            room: 1,
+            user: "john".into(),
+            message: "Hello, world!".into(),
+            time: get_time(),
+            parent_post_num: None,
+        };
+
+        let post = send_message(&post, &db).unwrap();
+
+        assert!(add_reaction(post.post_num, "john", "👍", &db).unwrap());
+        assert!(add_reaction(post.post_num, "jack", "🎉", &db).unwrap());
+        assert!(add_reaction(post.post_num, "jack", "👍", &db).unwrap());
+        //Reacting twice with the same emoji changes nothing
+        assert!(!add_reaction(post.post_num, "jack", "👍", &db).unwrap());
+
+        let reactions = get_post(post.post_num, &db).unwrap().reactions;
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].count, 2);
+        assert_eq!(reactions[0].users, vec!["john", "jack"]);
+        assert_eq!(count_reactions(post.post_num, "jack", &db).unwrap(), 2);
+
+        remove_reaction(post.post_num, "john", "👍", &db).unwrap();
+        assert_eq!(
+            get_reactions(post.post_num, &db).unwrap()[0].users,
+            vec!["jack"]
+        );
+
+        //Deleting the post clears them all
+        assert!(delete_post(post.post_num, &db)
+            .unwrap()
+            .reactions
+            .is_empty());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_threads() {
+        let db = test_db();
+
+        let post = |message: &str, parent_post_num| {
+            let post = super::super::types::InsertPost {
+                room: 1,
+                user: "john".into(),
+                message: message.into(),
+                time: get_time(),
+                parent_post_num,
+            };
+
+            send_message(&post, &db).unwrap().post_num
+        };
+
+        let first = post("First", None);
+        let reply = post("Reply", Some(first));
+        post("Another reply", Some(first));
+        let second = post("Second", None);
+
+        //Replies stay out of the room
+        let posts = get_posts(1, None, 10, &db).unwrap();
+        assert_eq!(
+            posts.iter().map(|post| post.post_num).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert_eq!(posts[0].reply_count, 2);
+        assert_eq!(posts[1].reply_count, 0);
+        assert_eq!(get_posts_after(1, first, 10, &db).unwrap().len(), 1);
+
+        //Asking for a reply gets its whole thread
+        let thread = get_thread(reply, &db).unwrap();
+        assert_eq!(thread.post.post_num, first);
+        assert_eq!(thread.replies.len(), 2);
+        assert_eq!(thread.replies[0].parent_post_num, Some(first));
+
+        //Deleted replies no longer count
+        delete_post(reply, &db).unwrap();
+        assert_eq!(get_post(first, &db).unwrap().reply_count, 1);
+        assert_eq!(get_thread(first, &db).unwrap().replies.len(), 2);
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_notifications() {
+        let db = test_db();
+
+        register_user("john", "password", &db).unwrap();
+        register_user("Jane", "password", &db).unwrap();
+
+        let post = |room, message: &str| {
+            let post = super::super::types::InsertPost {
+                room,
+                user: "john".into(),
+                message: message.into(),
+                time: get_time(),
+                parent_post_num: None,
+            };
+
+            let post = send_message(&post, &db).unwrap();
+            super::super::mention::record(&post, &db).unwrap();
+            post.post_num
+        };
+
+        //Nobody is notified about themselves or made up names
+        let first = post(1, "Hi @jane and @nobody, it's @john");
+        post(1, "@JANE again");
+        //Jane isn't in room 2, so she can't see this
+        post(2, "@jane?");
+
+        let notifications = get_notifications("Jane", 10, &db).unwrap();
+        assert_eq!(notifications.unread, 2);
+        assert_eq!(notifications.notifications[0].post.message, "@JANE again");
+        assert!(get_notifications("john", 10, &db)
+            .unwrap()
+            .notifications
+            .is_empty());
+
+        //Mentioning her again in an edit doesn't count twice
+        let edited = edit_post(first, "Hi @jane!", &db).unwrap();
+        super::super::mention::record(&edited, &db).unwrap();
+        assert_eq!(get_notifications("Jane", 10, &db).unwrap().unread, 2);
+
+        let mention_num = notifications.notifications[1].mention_num;
+        mark_notifications_read("Jane", Some(mention_num), &db).unwrap();
+        let notifications = get_notifications("Jane", 10, &db).unwrap();
+        assert_eq!(notifications.unread, 1);
+        assert!(notifications.notifications[1].read);
+
+        mark_notifications_read("Jane", None, &db).unwrap();
+        assert_eq!(get_notifications("Jane", 10, &db).unwrap().unread, 0);
+
+        //Deleting the post takes the notification with it
+        delete_post(first, &db).unwrap();
+        assert_eq!(
+            get_notifications("Jane", 10, &db)
+                .unwrap()
+                .notifications
+                .len(),
+            1
+        );
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_search() {
+        let db = test_db();
+
+        let post = |room, user: &str, message: &str, time| {
+            let post = super::super::types::InsertPost {
+                room,
+                user: user.into(),
+                message: message.into(),
+                time,
+                parent_post_num: None,
+            };
+
+            send_message(&post, &db).unwrap().post_num
+        };
+
+        //2024-01-01 and 2024-02-01
+        let first = post(1, "john", "Deploying the server tonight", 1704067200);
+        post(1, "jack", "Which servers?", 1706745600);
+        post(2, "jack", "Secret server talk", 1706745600);
+
+        let search = |terms: &str, author, from, to| {
+            let terms = super::super::search::to_match_query(terms).unwrap();
+
+            search_posts("john", &terms, author, from, to, 10, &db)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.post.post_num)
+                .collect::<Vec<_>>()
+        };
+
+        //Words match longer words they start, and john isn't in room 2
+        assert_eq!(search("server", None, None, None).len(), 2);
+        assert_eq!(search("server", Some("jack"), None, None).len(), 1);
+        assert_eq!(
+            search("server", None, None, Some("2024-01-01")),
+            vec![first]
+        );
+        assert!(search("server", None, Some("2024-03-01"), None).is_empty());
+        assert!(search("server talk", None, None, None).is_empty());
+
+        let terms = super::super::search::to_match_query("deploying").unwrap();
+        let result = &search_posts("john", &terms, None, None, None, 10, &db).unwrap()[0];
+        assert!(result
+            .snippet
+            .iter()
+            .any(|part| part.highlight && part.text == "Deploying"));
+
+        //Edits and deletes are picked up
+        edit_post(first, "Deploying the client tonight", &db).unwrap();
+        assert_eq!(search("client", None, None, None), vec![first]);
+        assert_eq!(search("server", None, None, None).len(), 1);
+
+        delete_post(first, &db).unwrap();
+        assert!(search("client", None, None, None).is_empty());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_attachments() {
+        let db = test_db();
+
+        let post = super::super::types::InsertPost {
+            room: 1,
+            user: "john".into(),
+            message: "Look".into(),
+            time: get_time(),
+            parent_post_num: None,
+        };
+
+        let file = |name: &str| super::super::attachment::UploadedFile {
+            name: name.into(),
+            mime: "text/plain".into(),
+            hash: super::super::attachment::hash(b"hello"),
+            data: b"hello".to_vec(),
+        };
+
+        let first = send_message_with_attachments(&post, &[file("a.txt")], &db).unwrap();
+        let second = send_message_with_attachments(&post, &[file("b.txt")], &db).unwrap();
+
+        assert_eq!(first.attachments.len(), 1);
+        assert_eq!(first.attachments[0].name, "a.txt");
+        assert_eq!(first.attachments[0].size, 5);
+        assert_eq!(
+            get_posts(1, None, 10, &db).unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+
+        let (attachment, room, hash) = get_attachment(second.attachments[0].attachment_num, &db)
+            .unwrap()
+            .unwrap();
+        assert_eq!(attachment, second.attachments[0]);
+        assert_eq!(room, 1);
+
+        //Both posts share the one file, which stays until neither uses it
+        assert_eq!(
+            delete_post(first.post_num, &db).unwrap().attachments,
+            vec![]
+        );
+        assert!(purge_unused_files(&db).unwrap().is_empty());
+
+        delete_post(second.post_num, &db).unwrap();
+        assert_eq!(purge_unused_files(&db).unwrap(), vec![hash]);
+        assert!(get_attachment(attachment.attachment_num, &db)
+            .unwrap()
+            .is_none());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_rooms() {
+        let db = test_db();
+
+        let room = create_room("team", "john", &db).unwrap();
+
+        //Names are unique
+        assert!(create_room("team", "jack", &db).is_err());
+
+        //Creator is in, everyone else has to join first
+        assert!(can_access_room("john", room.room_id, &db).unwrap());
+        assert!(!can_access_room("jack", room.room_id, &db).unwrap());
+        assert!(can_access_room("jack", 1, &db).unwrap());
+
+        let joined = |username| -> Vec<bool> {
+            get_rooms(username, &db)
+                .unwrap()
+                .iter()
+                .map(|room| room.joined)
+                .collect()
+        };
+
+        assert_eq!(joined("jack"), vec![true, false]);
+
+        join_room(room.room_id, "jack", &db).unwrap();
+        //Joining twice is harmless
+        join_room(room.room_id, "jack", &db).unwrap();
+
+        assert!(can_access_room("jack", room.room_id, &db).unwrap());
+        assert_eq!(joined("jack"), vec![true, true]);
+
+        for room in [1, room.room_id] {
+            let post = super::super::types::InsertPost {
+                room,
+                user: "john".into(),
+                message: "Hello, world!".into(),
+                time: get_time(),
+                parent_post_num: None,
+            };
+
+            send_message(&post, &db).unwrap();
+        }
+
+        //Only posts from rooms the user is in are visible
+        assert_eq!(
+            get_visible_posts_after("jack", 0, 10, &db).unwrap().len(),
+            2
+        );
+        assert_eq!(
+            get_visible_posts_after("jill", 0, 10, &db).unwrap().len(),
+            1
+        );
+        assert_eq!(get_visible_posts_after("jack", 0, 1, &db).unwrap().len(), 1);
+        assert_eq!(get_last_post_num(&db).unwrap(), 2);
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_direct_messages() {
+        let db = test_db();
+
+        for (from, to) in [("john", "jack"), ("jack", "john"), ("jill", "john")] {
+            let message = super::super::types::InsertDirectMessage {
+                from: from.into(),
+                to: to.into(),
+                message: "Hello!".into(),
+                time: get_time(),
+            };
+
+            send_direct_message(&message, &db).unwrap();
+        }
+
+        //Both sides see the same conversation
+        let john = get_direct_messages("john", "jack", None, 10, &db).unwrap();
+        let jack = get_direct_messages("jack", "john", None, 10, &db).unwrap();
+        assert_eq!(john.len(), 2);
+        assert_eq!(john, jack);
+
+        //Nobody else can see into it
+        assert!(get_direct_messages("jill", "jack", None, 10, &db)
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            get_direct_messages_after("john", "jack", 1, 10, &db)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        //Newest conversation first
+        let users: Vec<String> = get_conversations("john", &db)
+            .unwrap()
+            .into_iter()
+            .map(|conversation| conversation.user)
+            .collect();
+        assert_eq!(users, vec!["jill", "jack"]);
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_moderation() {
+        let db = test_db();
+
+        register_user("john", "password", &db).unwrap();
+        register_user("jack", "password", &db).unwrap();
+
+        //Everyone starts out as a plain user
+        assert_eq!(
+            get_role("john", &db).unwrap(),
+            (super::super::types::Role::User, false)
+        );
+
+        set_role("john", super::super::types::Role::Moderator, &db).unwrap();
+        assert_eq!(
+            get_role("john", &db).unwrap().0,
+            super::super::types::Role::Moderator
+        );
+
+        let session = generate_session("jack", "", "", &CONFIG, &db).unwrap();
+        assert!(validate_session("jack", &session, &db).unwrap());
+
+        //Banning kicks the user out too
+        set_banned("jack", true, &db).unwrap();
+        assert!(get_role("jack", &db).unwrap().1);
+        assert!(validate_session("jack", &session, &db).is_err());
+
+        set_banned("jack", false, &db).unwrap();
+        assert!(!get_role("jack", &db).unwrap().1);
+
+        log_moderation("john", "ban", "jack", "spam", &db).unwrap();
+        log_moderation("john", "unban", "jack", "", &db).unwrap();
+
+        let log = get_moderation_log(10, &db).unwrap();
+        assert_eq!(log.len(), 2);
+        //Newest first
+        assert_eq!(log[0].action, "unban");
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_admin() {
+        let db = test_db();
+
+        register_user("john", "password", &db).unwrap();
+        register_user("jack", "password", &db).unwrap();
+        generate_session("john", "", "", &CONFIG, &db).unwrap();
+        generate_session("jack", "", "", &CONFIG, &db).unwrap();
+
+        //2024-01-31, 2024-02-01 and 2024-02-02, all at 10:00 UTC
+        for (user, time) in [
+            ("john", 1706695200),
+            ("john", 1706781600),
+            ("jack", 1706868000),
+        ] {
+            let post = super::super::types::InsertPost {
+                room: 1,
+                user: user.into(),
+                message: "Hello, world!".into(),
+                time,
+                parent_post_num: None,
+            };
+
+            send_message(&post, &db).unwrap();
+        }
+
+        assert_eq!(list_sessions(None, None, &db).unwrap().len(), 2);
+        assert_eq!(list_sessions(Some("jack"), None, &db).unwrap().len(), 1);
+
+        //Renaming follows the user's posts and sessions
+        assert!(rename_user("john", "johnny", &db).unwrap());
+        assert!(!rename_user("nobody", "somebody", &db).unwrap());
+        assert!(rename_user("johnny", "jack", &db).is_err());
+        assert!(rename_user("johnny", "JACK", &db).is_err());
+        assert_eq!(list_sessions(Some("johnny"), None, &db).unwrap().len(), 1);
+        assert_eq!(get_post(1, &db).unwrap().user, "johnny");
+
+        //Resetting a password logs the user out
+        assert!(set_password("johnny", "new password", &db).unwrap());
+        assert!(list_sessions(Some("johnny"), None, &db).unwrap().is_empty());
+
+        assert_eq!(
+            purge_posts_between("2024-02-01", "2024-02-01", &db).unwrap(),
+            1
+        );
+        assert_eq!(purge_posts_by_user("jack", &db).unwrap(), 1);
+        assert_eq!(purge_post(1, &db).unwrap(), 1);
+        assert_eq!(purge_post(1, &db).unwrap(), 0);
+
+        assert!(delete_user("jack", &db).unwrap());
+        assert!(!delete_user("jack", &db).unwrap());
+
+        let users = list_users(&db).unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, "johnny");
+
+        let stats = get_stats(&db).unwrap();
+        assert_eq!((stats.users, stats.sessions, stats.posts), (1, 0, 0));
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_multiple_sessions() {
+        let db = test_db();
+
+        let laptop = generate_session("john", "Firefox", "127.0.0.1", &CONFIG, &db).unwrap();
+        let phone = generate_session("john", "Safari", "127.0.0.2", &CONFIG, &db).unwrap();
+        let tablet = generate_session("john", "Chrome", "127.0.0.3", &CONFIG, &db).unwrap();
+        let other = generate_session("jack", "Firefox", "127.0.0.4", &CONFIG, &db).unwrap();
+
+        //Logging in again doesn't log you out elsewhere
+        for session in [&laptop, &phone, &tablet] {
+            assert!(validate_session("john", session, &db).unwrap());
+        }
+        assert!(!validate_session("john", &other, &db).unwrap());
+
+        let sessions = list_sessions(Some("john"), Some(&laptop), &db).unwrap();
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions.iter().filter(|session| session.current).count(), 1);
+
+        let phone_num = sessions
+            .iter()
+            .find(|session| session.user_agent == "Safari")
+            .unwrap()
+            .session_num;
+
+        //Can't revoke someone else's session
+        assert!(!revoke_session("jack", phone_num, &db).unwrap());
+        assert!(revoke_session("john", phone_num, &db).unwrap());
+        assert!(!validate_session("john", &phone, &db).unwrap());
+
+        assert_eq!(revoke_other_sessions("john", &laptop, &db).unwrap(), 1);
+        assert!(validate_session("john", &laptop, &db).unwrap());
+        assert!(!validate_session("john", &tablet, &db).unwrap());
+
+        end_session(&laptop, &db).unwrap();
+        assert!(validate_session("john", &laptop, &db).is_err());
+        assert!(validate_session("jack", &other, &db).unwrap());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_session_expiry() {
+        let db = test_db();
+
+        let session = generate_session("john", "", "", &CONFIG, &db).unwrap();
+        let stale = generate_session("jack", "", "", &CONFIG, &db).unwrap();
+
+        //Activity slides the expiry forward by the idle timeout
+        assert_eq!(renew_session(&session, &CONFIG, &db).unwrap(), Some(60));
+
+        //But never past the maximum age
+        db.execute(
+            "UPDATE sessions SET createdAt = createdAt - 60 WHERE sessionId = ?;",
+            params![session],
+        )
+        .unwrap();
+        assert_eq!(renew_session(&session, &CONFIG, &db).unwrap(), Some(30));
+
+        //Expired sessions stay expired
+        db.execute(
+            "UPDATE sessions SET expiration = ? WHERE sessionId = ?;",
+            params![get_time() - 1, stale],
+        )
+        .unwrap();
+        assert_eq!(renew_session(&stale, &CONFIG, &db).unwrap(), None);
+        assert!(!validate_session("jack", &stale, &db).unwrap());
+
+        assert_eq!(purge_expired_sessions(&db).unwrap(), 1);
+        assert!(validate_session("jack", &stale, &db).is_err());
+        assert!(validate_session("john", &session, &db).unwrap());
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_login_failures() {
+        let db = test_db();
+
+        let limits = super::super::types::LoginLimitConfig {
+            max_failures: 4,
+            max_failures_per_ip: 10,
+            backoff_max: 3,
+            lockout: 100,
+        };
+        let now = get_time();
+
+        assert_eq!(get_login_lockout("user:john", &db).unwrap(), None);
+
+        //Backs off exponentially up to backoff_max, then locks out completely
+        assert_eq!(
+            record_login_failure("user:john", 4, &limits, &db).unwrap(),
+            now + 1
+        );
+        assert_eq!(
+            record_login_failure("user:john", 4, &limits, &db).unwrap(),
+            now + 2
+        );
+        assert_eq!(
+            record_login_failure("user:john", 4, &limits, &db).unwrap(),
+            now + 3
+        );
+        assert_eq!(
+            record_login_failure("user:john", 4, &limits, &db).unwrap(),
+            now + 100
+        );
+        assert_eq!(get_login_lockout("user:john", &db).unwrap(), Some(100));
+        assert_eq!(get_login_lockout("user:jack", &db).unwrap(), None);
+
+        //Failures are forgotten after a quiet spell
+        db.execute(
+            "UPDATE login_failures SET lastFailure = lastFailure - 101, lockedUntil = 0;",
+            params![],
+        )
+        .unwrap();
+        assert_eq!(
+            record_login_failure("user:john", 4, &limits, &db).unwrap(),
+            now + 1
+        );
+
+        clear_login_failures("user:john", &db).unwrap();
+        assert_eq!(get_login_lockout("user:john", &db).unwrap(), None);
+
+        record_login_failure("ip:127.0.0.1", 10, &limits, &db).unwrap();
+        db.execute(
+            "UPDATE login_failures SET lastFailure = lastFailure - 101, lockedUntil = 0;",
+            params![],
+        )
+        .unwrap();
+        assert_eq!(purge_login_failures(&limits, &db).unwrap(), 1);
+
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn test_validate_session() {
+        // Create a test session for validation
+        let test_session_id = uuid::Uuid::new_v4().to_string();
+        let test_username = "test_user";
+        let test_expiration = get_time() + 2 * 24 * 60 * 60;
+
+        let db = test_db();
 
         // Insert the test session into the database
         db.execute(
-            "INSERT INTO sessions VALUES (?, ?, ?);",
-            params![test_username, test_session_id, test_expiration],
+            "INSERT INTO sessions (username, sessionId, expiration, createdAt, lastSeen)
+                VALUES (?, ?, ?, ?, ?);",
+            params![
+                test_username,
+                test_session_id,
+                test_expiration,
+                get_time(),
+                get_time()
+            ],
         )
         .unwrap();
 